@@ -0,0 +1,390 @@
+pub mod resources;
+pub mod stats;
+pub mod systemd;
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use nix::unistd::Pid;
+use procfs::process::{MountInfo, Process};
+
+pub const DEFAULT_CGROUP_ROOT: &str = "/sys/fs/cgroup";
+const CGROUP_PROCS: &str = "cgroup.procs";
+const CGROUP_CONTROLLERS: &str = "cgroup.controllers";
+const CGROUP_SUBTREE_CONTROL: &str = "cgroup.subtree_control";
+const CGROUP_FREEZE: &str = "cgroup.freeze";
+const CGROUP_EVENTS: &str = "cgroup.events";
+const CGROUP_KILL: &str = "cgroup.kill";
+const FREEZER_STATE: &str = "freezer.state";
+const HUGEPAGES_DIR: &str = "/sys/kernel/mm/hugepages";
+
+/// How long `freeze` and `thaw` wait for the freezer to settle.
+const FREEZER_TIMEOUT: Duration = Duration::from_secs(5);
+const FREEZER_INTERVAL: Duration = Duration::from_millis(10);
+/// How long `remove` waits for the processes of a cgroup to be released.
+const REMOVE_TIMEOUT: Duration = Duration::from_secs(1);
+const REMOVE_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Returns true if the host uses the unified (v2) hierarchy.
+pub fn is_v2() -> bool {
+    Path::new(DEFAULT_CGROUP_ROOT)
+        .join(CGROUP_CONTROLLERS)
+        .exists()
+}
+
+/// Returns the cgroup path of a container. An empty `cgroups_path` in the
+/// spec means the runtime chooses one from the container id.
+pub fn path_of(cgroups_path: &str, container_id: &str) -> PathBuf {
+    if cgroups_path.is_empty() {
+        Path::new("/youki").join(container_id)
+    } else {
+        Path::new("/").join(cgroups_path)
+    }
+}
+
+/// Returns the directories of the cgroup `path`, one for each hierarchy
+/// mounted on the host.
+pub fn dirs(path: &Path) -> Result<Vec<PathBuf>> {
+    if is_v2() {
+        return Ok(vec![v2_dir(path)]);
+    }
+
+    let path = path.strip_prefix("/").unwrap_or(path);
+
+    let dirs = Process::myself()?
+        .mountinfo()?
+        .into_iter()
+        .filter(|m| m.fs_type == "cgroup")
+        .map(|m| m.mount_point.join(path))
+        .collect();
+    Ok(dirs)
+}
+
+/// Creates the cgroup `path` in every hierarchy and moves `pid` into it.
+pub fn create(path: &Path, pid: Pid) -> Result<()> {
+    if is_v2() {
+        let dir = v2_dir(path);
+        log::debug!("create cgroup {:?}", dir);
+        create_v2_dir(&dir)?;
+        return add_task(&dir, pid);
+    }
+
+    let cpuset_dir = v1_dir(path, "cpuset").ok();
+    for dir in dirs(path)? {
+        log::debug!("create cgroup {:?}", dir);
+        fs::create_dir_all(&dir)?;
+        if Some(&dir) == cpuset_dir.as_ref() {
+            inherit_cpuset(&dir)?;
+        }
+        add_task(&dir, pid)?;
+    }
+    Ok(())
+}
+
+/// Moves `pid` into the cgroup `path` in the v1 hierarchies where it isn't
+/// already, which once systemd started a unit for it are the ones systemd
+/// doesn't manage. Only the missing directories are created, so that the
+/// cgroups systemd owns above them are left as they are.
+pub fn join_v1(path: &Path, pid: Pid) -> Result<()> {
+    let cpuset_dir = v1_dir(path, "cpuset").ok();
+    for dir in dirs(path)? {
+        if has_task(&dir, pid)? {
+            continue;
+        }
+        log::debug!("join cgroup {:?}", dir);
+        create_missing_dirs(&dir, Some(&dir) == cpuset_dir.as_ref())?;
+        add_task(&dir, pid)?;
+    }
+    Ok(())
+}
+
+/// Creates `dir` and the parents of it that don't exist, copying the cpus
+/// and mems of the parent into each new directory in the cpuset hierarchy.
+fn create_missing_dirs(dir: &Path, cpuset: bool) -> Result<()> {
+    if dir.exists() {
+        return Ok(());
+    }
+    if let Some(parent) = dir.parent() {
+        create_missing_dirs(parent, cpuset)?;
+        fs::create_dir(dir)?;
+        if cpuset {
+            for file in &["cpuset.cpus", "cpuset.mems"] {
+                fs::write(
+                    dir.join(file),
+                    fs::read_to_string(parent.join(file))?.trim(),
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn has_task(dir: &Path, pid: Pid) -> Result<bool> {
+    match fs::read_to_string(dir.join(CGROUP_PROCS)) {
+        Ok(procs) => Ok(procs.lines().any(|line| line.trim() == pid.to_string())),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Creates `dir` in the unified hierarchy, delegating every available
+/// controller down to it so that its resources can be limited.
+fn create_v2_dir(dir: &Path) -> Result<()> {
+    let mut current = PathBuf::from(DEFAULT_CGROUP_ROOT);
+    for component in dir.strip_prefix(DEFAULT_CGROUP_ROOT)?.components() {
+        let controllers = fs::read_to_string(current.join(CGROUP_CONTROLLERS))?;
+        for controller in controllers.split_whitespace() {
+            // Some controllers can't be delegated, e.g. when the parent
+            // has processes of its own, which is not fatal.
+            if let Err(e) = fs::write(
+                current.join(CGROUP_SUBTREE_CONTROL),
+                format!("+{}", controller),
+            ) {
+                log::debug!("could not enable {} in {:?}: {}", controller, current, e);
+            }
+        }
+        current.push(component);
+        if !current.exists() {
+            fs::create_dir(&current)?;
+        }
+    }
+    Ok(())
+}
+
+/// A new cpuset cgroup in v1 starts with no cpus and mems, which makes it
+/// impossible to add tasks to it, so they are copied from the parent.
+fn inherit_cpuset(dir: &Path) -> Result<()> {
+    let parent = match dir.parent() {
+        Some(parent) => parent,
+        None => return Ok(()),
+    };
+    for file in &["cpuset.cpus", "cpuset.mems"] {
+        if !fs::read_to_string(dir.join(file))?.trim().is_empty() {
+            continue;
+        }
+        if fs::read_to_string(parent.join(file))?.trim().is_empty() {
+            inherit_cpuset(parent)?;
+        }
+        fs::write(
+            dir.join(file),
+            fs::read_to_string(parent.join(file))?.trim(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Returns the directory of the cgroup `path` in the unified hierarchy.
+pub fn v2_dir(path: &Path) -> PathBuf {
+    Path::new(DEFAULT_CGROUP_ROOT).join(path.strip_prefix("/").unwrap_or(path))
+}
+
+/// Returns the directory of the cgroup `path` in the v1 hierarchy that has
+/// `controller` attached.
+pub fn v1_dir(path: &Path, controller: &str) -> Result<PathBuf> {
+    let path = path.strip_prefix("/").unwrap_or(path);
+    let mount = Process::myself()?
+        .mountinfo()?
+        .into_iter()
+        .find(|m| m.fs_type == "cgroup" && has_controller(m, controller));
+    match mount {
+        Some(m) => Ok(m.mount_point.join(path)),
+        None => bail!("{} controller is not mounted", controller),
+    }
+}
+
+/// Returns the huge page sizes of the host in the form of the hugetlb
+/// controller, e.g. 2MB.
+pub fn hugepage_sizes() -> Vec<String> {
+    let entries = match fs::read_dir(HUGEPAGES_DIR) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            let kb: u64 = name
+                .strip_prefix("hugepages-")?
+                .strip_suffix("kB")?
+                .parse()
+                .ok()?;
+            Some(if kb.is_multiple_of(1024 * 1024) {
+                format!("{}GB", kb / (1024 * 1024))
+            } else if kb.is_multiple_of(1024) {
+                format!("{}MB", kb / 1024)
+            } else {
+                format!("{}KB", kb)
+            })
+        })
+        .collect()
+}
+
+/// Freezes every process in the cgroup `path` and waits until the kernel
+/// reports the cgroup as frozen. If it never does, the cgroup is thawed
+/// again rather than left partly frozen.
+pub fn freeze(path: &Path) -> Result<()> {
+    let frozen = try_freeze(path);
+    if frozen.is_err() {
+        if let Err(e) = thaw(path) {
+            log::warn!("failed to thaw {:?} after it failed to freeze: {}", path, e);
+        }
+    }
+    frozen
+}
+
+fn try_freeze(path: &Path) -> Result<()> {
+    if is_v2() {
+        let dir = v2_dir(path);
+        fs::write(dir.join(CGROUP_FREEZE), "1")?;
+        wait_for_freezer(|| Ok(read_events(&dir, "frozen")? == "1"))
+    } else {
+        let dir = v1_dir(path, "freezer")?;
+        wait_for_freezer(|| {
+            // A cgroup can get stuck in FREEZING while tasks are forking, so
+            // the request is repeated until it sticks.
+            fs::write(dir.join(FREEZER_STATE), "FROZEN")?;
+            Ok(fs::read_to_string(dir.join(FREEZER_STATE))?.trim() == "FROZEN")
+        })
+    }
+}
+
+/// Thaws every process in the cgroup `path` and waits until the kernel
+/// reports the cgroup as thawed.
+pub fn thaw(path: &Path) -> Result<()> {
+    if is_v2() {
+        let dir = v2_dir(path);
+        fs::write(dir.join(CGROUP_FREEZE), "0")?;
+        wait_for_freezer(|| Ok(read_events(&dir, "frozen")? == "0"))
+    } else {
+        let dir = v1_dir(path, "freezer")?;
+        fs::write(dir.join(FREEZER_STATE), "THAWED")?;
+        wait_for_freezer(|| Ok(fs::read_to_string(dir.join(FREEZER_STATE))?.trim() == "THAWED"))
+    }
+}
+
+fn wait_for_freezer<F: FnMut() -> Result<bool>>(mut settled: F) -> Result<()> {
+    let mut waited = Duration::from_millis(0);
+    while !settled()? {
+        if waited >= FREEZER_TIMEOUT {
+            bail!("timed out waiting for the freezer to settle");
+        }
+        thread::sleep(FREEZER_INTERVAL);
+        waited += FREEZER_INTERVAL;
+    }
+    Ok(())
+}
+
+/// Reads the value of `key` in the cgroup.events file of the v2 cgroup `dir`.
+fn read_events(dir: &Path, key: &str) -> Result<String> {
+    let events = fs::read_to_string(dir.join(CGROUP_EVENTS))?;
+    for line in events.lines() {
+        if let Some((k, v)) = line.split_once(' ') {
+            if k == key {
+                return Ok(v.trim().to_string());
+            }
+        }
+    }
+    bail!("{} is missing in {:?}", key, dir.join(CGROUP_EVENTS))
+}
+
+/// Returns true if the cgroup `path` has been created.
+pub fn exists(path: &Path) -> Result<bool> {
+    Ok(dirs(path)?.iter().any(|dir| dir.exists()))
+}
+
+/// Kills every process in the cgroup `path` with SIGKILL at once. It returns
+/// false if the kernel doesn't support it, which needs cgroup v2 and Linux
+/// 5.14.
+pub fn kill(path: &Path) -> Result<bool> {
+    let file = v2_dir(path).join(CGROUP_KILL);
+    if !is_v2() || !file.exists() {
+        return Ok(false);
+    }
+    fs::write(file, "1")?;
+    Ok(true)
+}
+
+/// Removes the cgroup `path` from every hierarchy. The kernel refuses to
+/// remove a cgroup until its last process has been released, so that is
+/// waited for. Directories that are already gone are skipped.
+pub fn remove(path: &Path) -> Result<()> {
+    for dir in dirs(path)? {
+        if !dir.exists() {
+            continue;
+        }
+        log::debug!("remove cgroup {:?}", dir);
+        let deadline = Instant::now() + REMOVE_TIMEOUT;
+        loop {
+            match fs::remove_dir(&dir) {
+                Ok(()) => break,
+                Err(e) if e.kind() == ErrorKind::NotFound => break,
+                Err(e) if e.raw_os_error() == Some(libc::EBUSY) && Instant::now() < deadline => {
+                    thread::sleep(REMOVE_INTERVAL)
+                }
+                Err(e) => bail!("failed to remove cgroup {:?}: {}", dir, e),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the processes in the cgroup `path`. Every hierarchy contains
+/// the same processes, so the first one is enough.
+pub fn pids(path: &Path) -> Result<Vec<Pid>> {
+    let mut pids = Vec::new();
+    if let Some(dir) = dirs(path)?.into_iter().find(|dir| dir.exists()) {
+        for line in fs::read_to_string(dir.join(CGROUP_PROCS))?.lines() {
+            pids.push(Pid::from_raw(line.trim().parse()?));
+        }
+    }
+    Ok(pids)
+}
+
+/// Returns the directories of the cgroups `pid` belongs to, one for each
+/// hierarchy mounted on the host.
+pub fn dirs_of(pid: Pid) -> Result<Vec<PathBuf>> {
+    let mountinfo = Process::myself()?.mountinfo()?;
+    let mut dirs = Vec::new();
+    for cgroup in Process::new(pid.as_raw())?.cgroups()? {
+        let mount = mountinfo.iter().find(|m| {
+            if cgroup.hierarchy == 0 {
+                m.fs_type == "cgroup2"
+            } else {
+                m.fs_type == "cgroup" && cgroup.controllers.iter().all(|c| has_controller(m, c))
+            }
+        });
+        match mount {
+            Some(m) => dirs.push(m.mount_point.join(cgroup.pathname.trim_start_matches('/'))),
+            None => log::debug!("hierarchy {} is not mounted", cgroup.hierarchy),
+        }
+    }
+    Ok(dirs)
+}
+
+fn has_controller(mount: &MountInfo, controller: &str) -> bool {
+    // named hierarchies such as `name=systemd` show up as a `name` option
+    match controller.split_once('=') {
+        Some((key, value)) => {
+            matches!(mount.super_options.get(key), Some(Some(v)) if v == value)
+        }
+        None => mount.super_options.contains_key(controller),
+    }
+}
+
+/// Moves `pid` into the cgroup directory `dir`.
+pub fn add_task(dir: &Path, pid: Pid) -> Result<()> {
+    fs::write(dir.join(CGROUP_PROCS), pid.to_string())?;
+    Ok(())
+}
+
+/// Moves `pid` into every cgroup `target` belongs to.
+pub fn join(target: Pid, pid: Pid) -> Result<()> {
+    for dir in dirs_of(target)? {
+        log::debug!("join {:?} to {:?}", pid, dir);
+        add_task(&dir, pid)?;
+    }
+    Ok(())
+}
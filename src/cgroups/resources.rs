@@ -0,0 +1,326 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+use crate::cgroups::{self, stats};
+use crate::spec::{LinuxBlockIO, LinuxCPU, LinuxMemory, LinuxResources, LinuxThrottleDevice};
+
+/// Applies the limits set in `resources` to the cgroup `path`. Fields that
+/// are not set are left untouched, so this serves both `create` and `update`.
+pub fn apply(path: &Path, resources: &LinuxResources) -> Result<()> {
+    if !resources.devices.is_empty() {
+        log::warn!("A feature of device cgroup is unimplemented.");
+    }
+    if cgroups::is_v2() {
+        apply_v2(&cgroups::v2_dir(path), resources)
+    } else {
+        apply_v1(path, resources)
+    }
+}
+
+fn apply_v1(path: &Path, resources: &LinuxResources) -> Result<()> {
+    if let Some(memory) = &resources.memory {
+        let dir = cgroups::v1_dir(path, "memory")?;
+        apply_v1_memory(&dir, memory)?;
+    }
+    if resources.disable_oom_killer {
+        let dir = cgroups::v1_dir(path, "memory")?;
+        write_file(&dir, "memory.oom_control", "1")?;
+    }
+
+    if let Some(cpu) = &resources.cpu {
+        let dir = cgroups::v1_dir(path, "cpu")?;
+        if let Some(shares) = cpu.shares {
+            write_file(&dir, "cpu.shares", &shares.to_string())?;
+        }
+        if let Some(period) = cpu.period {
+            write_file(&dir, "cpu.cfs_period_us", &period.to_string())?;
+        }
+        if let Some(quota) = cpu.quota {
+            write_file(&dir, "cpu.cfs_quota_us", &quota.to_string())?;
+        }
+        if let Some(period) = cpu.realtime_period {
+            write_file(&dir, "cpu.rt_period_us", &period.to_string())?;
+        }
+        if let Some(runtime) = cpu.realtime_runtime {
+            write_file(&dir, "cpu.rt_runtime_us", &runtime.to_string())?;
+        }
+        if !cpu.cpus.is_empty() || !cpu.mems.is_empty() {
+            apply_cpuset(&cgroups::v1_dir(path, "cpuset")?, cpu)?;
+        }
+    }
+
+    if let Some(pids) = &resources.pids {
+        let dir = cgroups::v1_dir(path, "pids")?;
+        write_file(&dir, "pids.max", &pids_limit(pids.limit))?;
+    }
+
+    if let Some(block_io) = &resources.block_io {
+        let dir = cgroups::v1_dir(path, "blkio")?;
+        if let Some(weight) = block_io.blkio_weight {
+            write_file(&dir, "blkio.weight", &weight.to_string())?;
+        }
+        if let Some(weight) = block_io.blkio_leaf_weight {
+            write_file(&dir, "blkio.leaf_weight", &weight.to_string())?;
+        }
+        for device in &block_io.blkio_weight_device {
+            if let Some(weight) = device.weight {
+                let value = format!("{}:{} {}", device.major, device.minor, weight);
+                write_file(&dir, "blkio.weight_device", &value)?;
+            }
+            if let Some(weight) = device.leaf_weight {
+                let value = format!("{}:{} {}", device.major, device.minor, weight);
+                write_file(&dir, "blkio.leaf_weight_device", &value)?;
+            }
+        }
+        let throttles = [
+            (
+                "blkio.throttle.read_bps_device",
+                &block_io.blkio_throttle_read_bps_device,
+            ),
+            (
+                "blkio.throttle.write_bps_device",
+                &block_io.blkio_throttle_write_bps_device,
+            ),
+            (
+                "blkio.throttle.read_iops_device",
+                &block_io.blkio_throttle_read_iops_device,
+            ),
+            (
+                "blkio.throttle.write_iops_device",
+                &block_io.blkio_throttle_write_iops_device,
+            ),
+        ];
+        for (file, devices) in throttles.iter() {
+            for device in devices.iter() {
+                let value = format!("{}:{} {}", device.major, device.minor, device.rate);
+                write_file(&dir, file, &value)?;
+            }
+        }
+    }
+
+    if !resources.hugepage_limits.is_empty() {
+        let dir = cgroups::v1_dir(path, "hugetlb")?;
+        for hugepage in &resources.hugepage_limits {
+            let file = format!("hugetlb.{}.limit_in_bytes", hugepage.page_size);
+            write_file(&dir, &file, &hugepage.limit.to_string())?;
+        }
+    }
+
+    if let Some(network) = &resources.network {
+        if let Some(class_id) = network.class_id {
+            let dir = cgroups::v1_dir(path, "net_cls")?;
+            write_file(&dir, "net_cls.classid", &class_id.to_string())?;
+        }
+        if !network.priorities.is_empty() {
+            let dir = cgroups::v1_dir(path, "net_prio")?;
+            for priority in &network.priorities {
+                let value = format!("{} {}", priority.name, priority.priority);
+                write_file(&dir, "net_prio.ifpriomap", &value)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn apply_v1_memory(dir: &Path, memory: &LinuxMemory) -> Result<()> {
+    // memory.memsw.limit_in_bytes must never be lower than
+    // memory.limit_in_bytes, so the order depends on which way they move.
+    match (memory.limit, memory.swap) {
+        (Some(limit), Some(swap)) => {
+            let current = stats::read_u64(&dir.join("memory.limit_in_bytes")).unwrap_or(0);
+            if swap == -1 || limit < 0 || limit as u64 > current {
+                write_file(dir, "memory.memsw.limit_in_bytes", &swap.to_string())?;
+                write_file(dir, "memory.limit_in_bytes", &limit.to_string())?;
+            } else {
+                write_file(dir, "memory.limit_in_bytes", &limit.to_string())?;
+                write_file(dir, "memory.memsw.limit_in_bytes", &swap.to_string())?;
+            }
+        }
+        (Some(limit), None) => write_file(dir, "memory.limit_in_bytes", &limit.to_string())?,
+        (None, Some(swap)) => write_file(dir, "memory.memsw.limit_in_bytes", &swap.to_string())?,
+        (None, None) => {}
+    }
+    if let Some(reservation) = memory.reservation {
+        write_file(dir, "memory.soft_limit_in_bytes", &reservation.to_string())?;
+    }
+    if let Some(kernel) = memory.kernel {
+        write_file(dir, "memory.kmem.limit_in_bytes", &kernel.to_string())?;
+    }
+    if let Some(kernel_tcp) = memory.kernel_tcp {
+        write_file(
+            dir,
+            "memory.kmem.tcp.limit_in_bytes",
+            &kernel_tcp.to_string(),
+        )?;
+    }
+    if let Some(swappiness) = memory.swappiness {
+        write_file(dir, "memory.swappiness", &swappiness.to_string())?;
+    }
+    Ok(())
+}
+
+fn apply_v2(dir: &Path, resources: &LinuxResources) -> Result<()> {
+    if let Some(memory) = &resources.memory {
+        if let Some(limit) = memory.limit {
+            write_file(dir, "memory.max", &max_or(limit))?;
+        }
+        if let Some(reservation) = memory.reservation {
+            write_file(dir, "memory.low", &max_or(reservation))?;
+        }
+        // OCI's swap is the limit of memory plus swap, while memory.swap.max
+        // only counts the swap.
+        if let Some(swap) = memory.swap {
+            let swap = match memory.limit {
+                _ if swap == -1 => "max".to_string(),
+                Some(limit) if limit >= 0 => {
+                    if swap < limit {
+                        bail!(
+                            "memory+swap limit {} is lower than memory limit {}",
+                            swap,
+                            limit
+                        );
+                    }
+                    (swap - limit).to_string()
+                }
+                _ => bail!("memory limit has to be set along with the swap limit"),
+            };
+            write_file(dir, "memory.swap.max", &swap)?;
+        }
+        if memory.kernel.is_some() || memory.kernel_tcp.is_some() || memory.swappiness.is_some() {
+            log::warn!("kernel memory and swappiness are not supported in cgroup v2");
+        }
+    }
+    if resources.disable_oom_killer {
+        log::warn!("disabling the OOM killer is not supported in cgroup v2");
+    }
+
+    if let Some(cpu) = &resources.cpu {
+        if let Some(shares) = cpu.shares {
+            write_file(dir, "cpu.weight", &shares_to_weight(shares).to_string())?;
+        }
+        if cpu.quota.is_some() || cpu.period.is_some() {
+            // cpu.max holds both values, so the one not given is kept
+            let current = fs::read_to_string(dir.join("cpu.max")).unwrap_or_default();
+            let current: Vec<&str> = current.split_whitespace().collect();
+            let quota = match cpu.quota {
+                Some(quota) => max_or(quota),
+                None => current.first().unwrap_or(&"max").to_string(),
+            };
+            let period = match cpu.period {
+                Some(period) => period.to_string(),
+                None => current.get(1).unwrap_or(&"100000").to_string(),
+            };
+            write_file(dir, "cpu.max", &format!("{} {}", quota, period))?;
+        }
+        if cpu.realtime_period.is_some() || cpu.realtime_runtime.is_some() {
+            log::warn!("realtime scheduling is not supported in cgroup v2");
+        }
+        apply_cpuset(dir, cpu)?;
+    }
+
+    if let Some(pids) = &resources.pids {
+        write_file(dir, "pids.max", &pids_limit(pids.limit))?;
+    }
+
+    if let Some(block_io) = &resources.block_io {
+        apply_v2_io(dir, block_io)?;
+    }
+
+    for hugepage in &resources.hugepage_limits {
+        let file = format!("hugetlb.{}.max", hugepage.page_size);
+        write_file(dir, &file, &hugepage.limit.to_string())?;
+    }
+
+    if resources.network.is_some() {
+        log::warn!("network resources are not supported in cgroup v2");
+    }
+    Ok(())
+}
+
+fn apply_v2_io(dir: &Path, block_io: &LinuxBlockIO) -> Result<()> {
+    if let Some(weight) = block_io.blkio_weight {
+        write_file(
+            dir,
+            "io.weight",
+            &blkio_weight_to_io_weight(weight).to_string(),
+        )?;
+    }
+    for device in &block_io.blkio_weight_device {
+        if let Some(weight) = device.weight {
+            let value = format!(
+                "{}:{} {}",
+                device.major,
+                device.minor,
+                blkio_weight_to_io_weight(weight)
+            );
+            write_file(dir, "io.weight", &value)?;
+        }
+    }
+    let throttles: [(&str, &Vec<LinuxThrottleDevice>); 4] = [
+        ("rbps", &block_io.blkio_throttle_read_bps_device),
+        ("wbps", &block_io.blkio_throttle_write_bps_device),
+        ("riops", &block_io.blkio_throttle_read_iops_device),
+        ("wiops", &block_io.blkio_throttle_write_iops_device),
+    ];
+    for (key, devices) in throttles.iter() {
+        for device in devices.iter() {
+            let value = format!("{}:{} {}={}", device.major, device.minor, key, device.rate);
+            write_file(dir, "io.max", &value)?;
+        }
+    }
+    Ok(())
+}
+
+fn apply_cpuset(dir: &Path, cpu: &LinuxCPU) -> Result<()> {
+    if !cpu.cpus.is_empty() {
+        write_file(dir, "cpuset.cpus", &cpu.cpus)?;
+    }
+    if !cpu.mems.is_empty() {
+        write_file(dir, "cpuset.mems", &cpu.mems)?;
+    }
+    Ok(())
+}
+
+/// Converts cpu.shares of v1, in [2, 262144], to cpu.weight of v2, in
+/// [1, 10000].
+pub(crate) fn shares_to_weight(shares: u64) -> u64 {
+    if shares == 0 {
+        return 100;
+    }
+    1 + (shares.clamp(2, 262_144) - 2) * 9999 / 262_142
+}
+
+/// Converts blkio.weight of v1, in [10, 1000], to io.weight of v2, in
+/// [1, 10000].
+pub(crate) fn blkio_weight_to_io_weight(weight: u16) -> u64 {
+    1 + (weight.clamp(10, 1000) as u64 - 10) * 9999 / 990
+}
+
+fn pids_limit(limit: i64) -> String {
+    if limit > 0 {
+        limit.to_string()
+    } else {
+        "max".to_string()
+    }
+}
+
+fn max_or(value: i64) -> String {
+    if value == -1 {
+        "max".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Writes `value` to `file` in the cgroup directory `dir`, turning the errno
+/// the kernel rejects a value with into an error that names the value.
+fn write_file(dir: &Path, file: &str, value: &str) -> Result<()> {
+    let path = dir.join(file);
+    log::debug!("write {} to {:?}", value, path);
+    if let Err(e) = fs::write(&path, value) {
+        bail!("failed to set {} to {}: {}", file, value, e);
+    }
+    Ok(())
+}
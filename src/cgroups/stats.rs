@@ -0,0 +1,390 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::cgroups;
+
+/// Resource usage of a cgroup, laid out like the `data` of runc's `stats`
+/// events so that existing collectors can consume it.
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Stats {
+    pub cpu: CpuStats,
+    pub memory: MemoryStats,
+    pub pids: PidsStats,
+    pub blkio: BlkioStats,
+    /// keyed by page size, e.g. 2MB
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub hugetlb: HashMap<String, HugetlbStats>,
+}
+
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CpuStats {
+    pub usage: CpuUsage,
+    pub throttling: Throttling,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub psi: Option<PsiStats>,
+}
+
+/// CPU time in nanoseconds.
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CpuUsage {
+    pub total: u64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub percpu: Vec<u64>,
+    pub kernel: u64,
+    pub user: u64,
+}
+
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Throttling {
+    pub periods: u64,
+    pub throttled_periods: u64,
+    pub throttled_time: u64,
+}
+
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryStats {
+    pub cache: u64,
+    pub usage: MemoryEntry,
+    pub swap: MemoryEntry,
+    /// how often the cgroup ran out of memory, only counted by cgroup v2
+    pub oom: u64,
+    /// how many processes the OOM killer killed
+    pub oom_kill: u64,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub raw: HashMap<String, u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub psi: Option<PsiStats>,
+}
+
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryEntry {
+    pub limit: u64,
+    pub usage: u64,
+    pub max: u64,
+    pub failcnt: u64,
+}
+
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PidsStats {
+    pub current: u64,
+    pub limit: u64,
+}
+
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BlkioStats {
+    pub io_service_bytes_recursive: Vec<BlkioEntry>,
+    pub io_serviced_recursive: Vec<BlkioEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub psi: Option<PsiStats>,
+}
+
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BlkioEntry {
+    pub major: u64,
+    pub minor: u64,
+    pub op: String,
+    pub value: u64,
+}
+
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HugetlbStats {
+    pub usage: u64,
+    pub max: u64,
+    pub failcnt: u64,
+}
+
+/// Pressure stall information, only kept per cgroup by cgroup v2.
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PsiStats {
+    pub some: PsiData,
+    pub full: PsiData,
+}
+
+/// The share of time tasks were stalled, as a percentage averaged over 10,
+/// 60 and 300 seconds, and the total stall time in microseconds.
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PsiData {
+    pub avg10: f64,
+    pub avg60: f64,
+    pub avg300: f64,
+    pub total: u64,
+}
+
+/// Collects the resource usage of the cgroup `path`. Controllers that are
+/// not available on the host are left zeroed.
+pub fn collect(path: &Path) -> Result<Stats> {
+    if cgroups::is_v2() {
+        Ok(collect_v2(&cgroups::v2_dir(path)))
+    } else {
+        Ok(collect_v1(path))
+    }
+}
+
+/// Returns how many processes of the cgroup `path` were killed by the OOM
+/// killer.
+pub fn oom_kill_count(path: &Path) -> Result<u64> {
+    let events = if cgroups::is_v2() {
+        cgroups::v2_dir(path).join("memory.events")
+    } else {
+        cgroups::v1_dir(path, "memory")?.join("memory.oom_control")
+    };
+    Ok(read_keyed(&events).get("oom_kill").copied().unwrap_or(0))
+}
+
+fn collect_v2(dir: &Path) -> Stats {
+    let mut stats = Stats::default();
+
+    let cpu = read_keyed(&dir.join("cpu.stat"));
+    let usec = |key: &str| cpu.get(key).copied().unwrap_or(0) * 1000;
+    stats.cpu.usage.total = usec("usage_usec");
+    stats.cpu.usage.user = usec("user_usec");
+    stats.cpu.usage.kernel = usec("system_usec");
+    stats.cpu.throttling.periods = cpu.get("nr_periods").copied().unwrap_or(0);
+    stats.cpu.throttling.throttled_periods = cpu.get("nr_throttled").copied().unwrap_or(0);
+    stats.cpu.throttling.throttled_time = usec("throttled_usec");
+    stats.cpu.psi = read_psi(&dir.join("cpu.pressure"));
+
+    let memory = read_keyed(&dir.join("memory.stat"));
+    stats.memory.cache = memory.get("file").copied().unwrap_or(0);
+    stats.memory.raw = memory;
+    stats.memory.usage.usage = read_u64(&dir.join("memory.current")).unwrap_or(0);
+    stats.memory.usage.limit = read_u64(&dir.join("memory.max")).unwrap_or(0);
+    stats.memory.usage.max = read_u64(&dir.join("memory.peak")).unwrap_or(0);
+    let events = read_keyed(&dir.join("memory.events"));
+    stats.memory.usage.failcnt = events.get("max").copied().unwrap_or(0);
+    stats.memory.oom = events.get("oom").copied().unwrap_or(0);
+    stats.memory.oom_kill = events.get("oom_kill").copied().unwrap_or(0);
+    stats.memory.swap.usage = read_u64(&dir.join("memory.swap.current")).unwrap_or(0);
+    stats.memory.swap.limit = read_u64(&dir.join("memory.swap.max")).unwrap_or(0);
+    stats.memory.psi = read_psi(&dir.join("memory.pressure"));
+
+    stats.pids.current = read_u64(&dir.join("pids.current")).unwrap_or(0);
+    stats.pids.limit = read_u64(&dir.join("pids.max")).unwrap_or(0);
+
+    // 8:0 rbytes=1459200 wbytes=314773504 rios=192 wios=353 dbytes=0 dios=0
+    for line in read_lines(&dir.join("io.stat")) {
+        let mut fields = line.split_whitespace();
+        let (major, minor) = match fields.next().and_then(parse_device) {
+            Some(device) => device,
+            None => continue,
+        };
+        for field in fields {
+            let (key, value) = match field.split_once('=') {
+                Some((key, value)) => (key, value.parse().unwrap_or(0)),
+                None => continue,
+            };
+            let (entries, op) = match key {
+                "rbytes" => (&mut stats.blkio.io_service_bytes_recursive, "Read"),
+                "wbytes" => (&mut stats.blkio.io_service_bytes_recursive, "Write"),
+                "rios" => (&mut stats.blkio.io_serviced_recursive, "Read"),
+                "wios" => (&mut stats.blkio.io_serviced_recursive, "Write"),
+                _ => continue,
+            };
+            entries.push(BlkioEntry {
+                major,
+                minor,
+                op: op.to_string(),
+                value,
+            });
+        }
+    }
+    stats.blkio.psi = read_psi(&dir.join("io.pressure"));
+
+    for size in cgroups::hugepage_sizes() {
+        let prefix = format!("hugetlb.{}", size);
+        let usage = match read_u64(&dir.join(format!("{}.current", prefix))) {
+            Some(usage) => usage,
+            None => continue,
+        };
+        // v2 keeps no peak usage
+        let failcnt = read_keyed(&dir.join(format!("{}.events", prefix)))
+            .get("max")
+            .copied()
+            .unwrap_or(0);
+        stats.hugetlb.insert(
+            size,
+            HugetlbStats {
+                usage,
+                max: 0,
+                failcnt,
+            },
+        );
+    }
+
+    stats
+}
+
+fn collect_v1(path: &Path) -> Stats {
+    let mut stats = Stats::default();
+
+    if let Ok(dir) = cgroups::v1_dir(path, "cpuacct") {
+        stats.cpu.usage.total = read_u64(&dir.join("cpuacct.usage")).unwrap_or(0);
+        stats.cpu.usage.percpu = fs::read_to_string(dir.join("cpuacct.usage_percpu"))
+            .map(|s| {
+                s.split_whitespace()
+                    .filter_map(|v| v.parse().ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        // cpuacct.stat is in clock ticks
+        let ticks = read_keyed(&dir.join("cpuacct.stat"));
+        let nanos_per_tick = 1_000_000_000 / procfs::ticks_per_second().unwrap_or(100) as u64;
+        stats.cpu.usage.user = ticks.get("user").copied().unwrap_or(0) * nanos_per_tick;
+        stats.cpu.usage.kernel = ticks.get("system").copied().unwrap_or(0) * nanos_per_tick;
+    }
+    if let Ok(dir) = cgroups::v1_dir(path, "cpu") {
+        let cpu = read_keyed(&dir.join("cpu.stat"));
+        stats.cpu.throttling.periods = cpu.get("nr_periods").copied().unwrap_or(0);
+        stats.cpu.throttling.throttled_periods = cpu.get("nr_throttled").copied().unwrap_or(0);
+        stats.cpu.throttling.throttled_time = cpu.get("throttled_time").copied().unwrap_or(0);
+    }
+
+    if let Ok(dir) = cgroups::v1_dir(path, "memory") {
+        let memory = read_keyed(&dir.join("memory.stat"));
+        stats.memory.cache = memory.get("cache").copied().unwrap_or(0);
+        stats.memory.raw = memory;
+        stats.memory.usage = read_memory_entry(&dir, "memory");
+        stats.memory.swap = read_memory_entry(&dir, "memory.memsw");
+        stats.memory.oom_kill = read_keyed(&dir.join("memory.oom_control"))
+            .get("oom_kill")
+            .copied()
+            .unwrap_or(0);
+    }
+
+    if let Ok(dir) = cgroups::v1_dir(path, "pids") {
+        stats.pids.current = read_u64(&dir.join("pids.current")).unwrap_or(0);
+        stats.pids.limit = read_u64(&dir.join("pids.max")).unwrap_or(0);
+    }
+
+    if let Ok(dir) = cgroups::v1_dir(path, "blkio") {
+        stats.blkio.io_service_bytes_recursive =
+            read_blkio_entries(&dir.join("blkio.throttle.io_service_bytes"));
+        stats.blkio.io_serviced_recursive =
+            read_blkio_entries(&dir.join("blkio.throttle.io_serviced"));
+    }
+
+    if let Ok(dir) = cgroups::v1_dir(path, "hugetlb") {
+        for size in cgroups::hugepage_sizes() {
+            let read = |name: &str| read_u64(&dir.join(format!("hugetlb.{}.{}", size, name)));
+            let usage = match read("usage_in_bytes") {
+                Some(usage) => usage,
+                None => continue,
+            };
+            let entry = HugetlbStats {
+                usage,
+                max: read("max_usage_in_bytes").unwrap_or(0),
+                failcnt: read("failcnt").unwrap_or(0),
+            };
+            stats.hugetlb.insert(size, entry);
+        }
+    }
+
+    stats
+}
+
+fn read_memory_entry(dir: &Path, prefix: &str) -> MemoryEntry {
+    let read = |name: &str| read_u64(&dir.join(format!("{}.{}", prefix, name))).unwrap_or(0);
+    MemoryEntry {
+        limit: read("limit_in_bytes"),
+        usage: read("usage_in_bytes"),
+        max: read("max_usage_in_bytes"),
+        failcnt: read("failcnt"),
+    }
+}
+
+// 8:0 Read 1459200
+fn read_blkio_entries(path: &Path) -> Vec<BlkioEntry> {
+    read_lines(path)
+        .iter()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [device, op, value] => {
+                    let (major, minor) = parse_device(device)?;
+                    Some(BlkioEntry {
+                        major,
+                        minor,
+                        op: op.to_string(),
+                        value: value.parse().ok()?,
+                    })
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+// some avg10=0.00 avg60=0.00 avg300=0.00 total=0
+// full avg10=0.00 avg60=0.00 avg300=0.00 total=0
+fn read_psi(path: &Path) -> Option<PsiStats> {
+    let lines = read_lines(path);
+    if lines.is_empty() {
+        return None;
+    }
+    let mut psi = PsiStats::default();
+    for line in lines {
+        let mut fields = line.split_whitespace();
+        let data = match fields.next() {
+            Some("some") => &mut psi.some,
+            Some("full") => &mut psi.full,
+            _ => continue,
+        };
+        for field in fields {
+            match field.split_once('=') {
+                Some(("avg10", value)) => data.avg10 = value.parse().unwrap_or(0.0),
+                Some(("avg60", value)) => data.avg60 = value.parse().unwrap_or(0.0),
+                Some(("avg300", value)) => data.avg300 = value.parse().unwrap_or(0.0),
+                Some(("total", value)) => data.total = value.parse().unwrap_or(0),
+                _ => {}
+            }
+        }
+    }
+    Some(psi)
+}
+
+fn parse_device(device: &str) -> Option<(u64, u64)> {
+    let (major, minor) = device.split_once(':')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+/// Reads a file holding a single value, where `max` stands for no limit.
+pub(crate) fn read_u64(path: &Path) -> Option<u64> {
+    let value = fs::read_to_string(path).ok()?;
+    match value.trim() {
+        "max" => Some(u64::MAX),
+        value => value.parse().ok(),
+    }
+}
+
+/// Reads a file made of `key value` lines.
+pub(crate) fn read_keyed(path: &Path) -> HashMap<String, u64> {
+    read_lines(path)
+        .iter()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(' ')?;
+            Some((key.to_string(), value.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+fn read_lines(path: &Path) -> Vec<String> {
+    fs::read_to_string(path)
+        .map(|s| s.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
@@ -0,0 +1,523 @@
+//! Manages the cgroup of a container as a transient scope unit of systemd,
+//! so that systemd knows about it instead of fighting over the hierarchy.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use nix::unistd::Pid;
+
+use crate::cgroups;
+use crate::cgroups::resources::{blkio_weight_to_io_weight, shares_to_weight};
+use crate::dbus::{Connection, Message, Value, SIGNAL};
+use crate::spec::LinuxResources;
+
+const SYSTEMD_DESTINATION: &str = "org.freedesktop.systemd1";
+const SYSTEMD_PATH: &str = "/org/freedesktop/systemd1";
+const MANAGER_INTERFACE: &str = "org.freedesktop.systemd1.Manager";
+const NO_SUCH_UNIT: &str = "org.freedesktop.systemd1.NoSuchUnit";
+
+/// Exists only when systemd is the init of the host, see sd_booted(3).
+const SYSTEMD_RUNTIME_DIR: &str = "/run/systemd/system";
+
+const DEFAULT_SLICE: &str = "system.slice";
+const DEFAULT_PREFIX: &str = "youki";
+/// How long to wait for systemd to finish starting or stopping a unit.
+const JOB_TIMEOUT: Duration = Duration::from_secs(30);
+/// The CPU period systemd assumes when none is given, in microseconds.
+const DEFAULT_CPU_PERIOD: u64 = 100_000;
+
+/// Returns true if the host is booted with systemd, which --systemd-cgroup
+/// needs.
+pub fn is_booted() -> bool {
+    Path::new(SYSTEMD_RUNTIME_DIR).is_dir()
+}
+
+/// The unit of a container, parsed from `linux.cgroupsPath` in the
+/// `slice:prefix:name` form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unit {
+    pub slice: String,
+    pub name: String,
+}
+
+impl Unit {
+    /// Parses `cgroups_path`. An empty path, or empty parts of it, fall back
+    /// to a scope named after the container in system.slice. A name ending
+    /// in .slice is used as the unit as is, otherwise the unit is the scope
+    /// `<prefix>-<name>.scope`.
+    pub fn parse(cgroups_path: &str, container_id: &str) -> Result<Self> {
+        let parts: Vec<&str> = if cgroups_path.is_empty() {
+            vec!["", "", ""]
+        } else {
+            cgroups_path.split(':').collect()
+        };
+        if parts.len() != 3 {
+            bail!(
+                "cgroupsPath {:?} is not in the slice:prefix:name form of --systemd-cgroup",
+                cgroups_path
+            );
+        }
+        let slice = match parts[0] {
+            "" => DEFAULT_SLICE,
+            slice if slice.ends_with(".slice") => slice,
+            slice => bail!("{:?} is not a slice", slice),
+        };
+        let prefix = match parts[1] {
+            "" => DEFAULT_PREFIX,
+            prefix => prefix,
+        };
+        let name = match parts[2] {
+            "" => container_id,
+            name => name,
+        };
+        let name = if name.ends_with(".slice") {
+            name.to_string()
+        } else {
+            format!("{}-{}.scope", prefix, name)
+        };
+        Ok(Self {
+            slice: slice.to_string(),
+            name,
+        })
+    }
+
+    /// Returns the cgroup systemd creates for the unit, relative to the root
+    /// of each hierarchy.
+    pub fn cgroup_path(&self) -> Result<PathBuf> {
+        Ok(expand_slice(&self.slice)?.join(&self.name))
+    }
+}
+
+/// Returns the cgroup of `slice`, which is nested in the slices named by the
+/// dash-separated prefixes of its name, e.g. /a.slice/a-b.slice for a-b.slice.
+fn expand_slice(slice: &str) -> Result<PathBuf> {
+    let mut path = PathBuf::from("/");
+    if slice == "-.slice" {
+        return Ok(path);
+    }
+    let name = slice.trim_end_matches(".slice");
+    if name.is_empty() || name.starts_with('-') || name.ends_with('-') || name.contains("--") {
+        bail!("{:?} is not a valid slice name", slice);
+    }
+    let mut prefix = String::new();
+    for component in name.split('-') {
+        if !prefix.is_empty() {
+            prefix.push('-');
+        }
+        prefix.push_str(component);
+        path.push(format!("{}.slice", prefix));
+    }
+    Ok(path)
+}
+
+/// Starts `unit` as a transient scope containing `pid`, limited by
+/// `resources`, and waits until systemd has started it. The caller can then
+/// treat the cgroup of the unit like any other.
+pub fn start_unit(unit: &Unit, pid: Pid, resources: Option<&LinuxResources>) -> Result<()> {
+    let mut connection = Connection::system()?;
+    subscribe(&mut connection)?;
+
+    let mut properties = vec![
+        property(
+            "Description",
+            Value::Str(format!("youki container {}", unit.name)),
+        ),
+        property("Slice", Value::Str(unit.slice.clone())),
+        property(
+            "PIDs",
+            Value::Array("u".to_string(), vec![Value::UInt32(pid.as_raw() as u32)]),
+        ),
+        // the runtime sets up the cgroup below the unit on its own
+        property("Delegate", Value::Bool(true)),
+        property("DefaultDependencies", Value::Bool(false)),
+        property("MemoryAccounting", Value::Bool(true)),
+        property("CPUAccounting", Value::Bool(true)),
+        property("TasksAccounting", Value::Bool(true)),
+    ];
+    if cgroups::is_v2() {
+        properties.push(property("IOAccounting", Value::Bool(true)));
+    } else {
+        properties.push(property("BlockIOAccounting", Value::Bool(true)));
+    }
+    if let Some(resources) = resources {
+        properties.extend(resource_properties(resources));
+    }
+
+    log::debug!("start systemd unit {} in {}", unit.name, unit.slice);
+    let reply = connection.call(manager_call(
+        "StartTransientUnit",
+        vec![
+            Value::Str(unit.name.clone()),
+            Value::Str("replace".to_string()),
+            Value::Array("(sv)".to_string(), properties),
+            // auxiliary units
+            Value::Array("(sa(sv))".to_string(), Vec::new()),
+        ],
+    ))?;
+    wait_for_job(&mut connection, &reply, &unit.name)
+}
+
+/// Stops `unit`, which removes its cgroup, and waits until systemd has
+/// stopped it. A unit that doesn't exist, e.g. because it already stopped
+/// on its own once it was empty, is not an error.
+pub fn stop_unit(name: &str) -> Result<()> {
+    let mut connection = Connection::system()?;
+    subscribe(&mut connection)?;
+
+    log::debug!("stop systemd unit {}", name);
+    let reply = match connection.call(manager_call(
+        "StopUnit",
+        vec![
+            Value::Str(name.to_string()),
+            Value::Str("replace".to_string()),
+        ],
+    )) {
+        Ok(reply) => reply,
+        Err(e) if e.to_string().starts_with(NO_SUCH_UNIT) => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    wait_for_job(&mut connection, &reply, name)
+}
+
+/// Sets the limits in `resources` on the running unit `name`, so that
+/// systemd doesn't reset them when it reloads.
+pub fn set_unit_properties(name: &str, resources: &LinuxResources) -> Result<()> {
+    let properties = resource_properties(resources);
+    if properties.is_empty() {
+        return Ok(());
+    }
+    let mut connection = Connection::system()?;
+    log::debug!("set properties of systemd unit {}", name);
+    connection.call(manager_call(
+        "SetUnitProperties",
+        vec![
+            Value::Str(name.to_string()),
+            // runtime only, not persisted across reboots
+            Value::Bool(true),
+            Value::Array("(sv)".to_string(), properties),
+        ],
+    ))?;
+    Ok(())
+}
+
+/// Translates `resources` into the properties of a unit. The cgroup of the
+/// unit is systemd's to write, so what it has no property for is not
+/// applied, with a warning.
+fn resource_properties(resources: &LinuxResources) -> Vec<Value> {
+    for field in ignored_resources(resources) {
+        log::warn!("linux.resources.{} is ignored with --systemd-cgroup", field);
+    }
+    let v2 = cgroups::is_v2();
+    let mut properties = Vec::new();
+
+    if let Some(memory) = &resources.memory {
+        if let Some(limit) = memory.limit {
+            let name = if v2 { "MemoryMax" } else { "MemoryLimit" };
+            properties.push(property(name, Value::UInt64(infinity_or(limit))));
+        }
+        if let (true, Some(reservation)) = (v2, memory.reservation) {
+            properties.push(property(
+                "MemoryLow",
+                Value::UInt64(infinity_or(reservation)),
+            ));
+        }
+    }
+
+    if let Some(cpu) = &resources.cpu {
+        if let Some(shares) = cpu.shares {
+            if v2 {
+                properties.push(property(
+                    "CPUWeight",
+                    Value::UInt64(shares_to_weight(shares)),
+                ));
+            } else {
+                properties.push(property("CPUShares", Value::UInt64(shares)));
+            }
+        }
+        if let Some(period) = cpu.period {
+            properties.push(property("CPUQuotaPeriodUSec", Value::UInt64(period)));
+        }
+        if let Some(quota) = cpu.quota {
+            let per_sec = if quota > 0 {
+                let period = cpu.period.filter(|p| *p > 0).unwrap_or(DEFAULT_CPU_PERIOD);
+                // systemd works in steps of 10ms per second, so round up
+                // rather than leave the container with less than it asked for
+                let per_sec = quota as u64 * 1_000_000 / period;
+                per_sec.div_ceil(10_000) * 10_000
+            } else {
+                u64::MAX
+            };
+            properties.push(property("CPUQuotaPerSecUSec", Value::UInt64(per_sec)));
+        }
+    }
+
+    if let Some(pids) = &resources.pids {
+        let limit = if pids.limit > 0 {
+            pids.limit as u64
+        } else {
+            u64::MAX
+        };
+        properties.push(property("TasksMax", Value::UInt64(limit)));
+    }
+
+    if let Some(weight) = resources.block_io.as_ref().and_then(|b| b.blkio_weight) {
+        if v2 {
+            let weight = blkio_weight_to_io_weight(weight);
+            properties.push(property("IOWeight", Value::UInt64(weight)));
+        } else {
+            properties.push(property("BlockIOWeight", Value::UInt64(weight as u64)));
+        }
+    }
+
+    properties
+}
+
+/// Returns the fields set in `resources` that `resource_properties` has no
+/// property for.
+fn ignored_resources(resources: &LinuxResources) -> Vec<&'static str> {
+    let v2 = cgroups::is_v2();
+    let mut ignored = Vec::new();
+    if !resources.devices.is_empty() {
+        ignored.push("devices");
+    }
+    if resources.disable_oom_killer {
+        ignored.push("disableOomKiller");
+    }
+    if let Some(memory) = &resources.memory {
+        let fields = [
+            ("memory.reservation", !v2 && memory.reservation.is_some()),
+            ("memory.swap", memory.swap.is_some()),
+            ("memory.kernel", memory.kernel.is_some()),
+            ("memory.kernelTCP", memory.kernel_tcp.is_some()),
+            ("memory.swappiness", memory.swappiness.is_some()),
+        ];
+        ignored.extend(
+            fields
+                .iter()
+                .filter(|(_, set)| *set)
+                .map(|(field, _)| *field),
+        );
+    }
+    if let Some(cpu) = &resources.cpu {
+        let fields = [
+            ("cpu.realtimeRuntime", cpu.realtime_runtime.is_some()),
+            ("cpu.realtimePeriod", cpu.realtime_period.is_some()),
+            ("cpu.cpus", !cpu.cpus.is_empty()),
+            ("cpu.mems", !cpu.mems.is_empty()),
+        ];
+        ignored.extend(
+            fields
+                .iter()
+                .filter(|(_, set)| *set)
+                .map(|(field, _)| *field),
+        );
+    }
+    if let Some(block_io) = &resources.block_io {
+        let fields = [
+            (
+                "blockIO.blkioLeafWeight",
+                block_io.blkio_leaf_weight.is_some(),
+            ),
+            (
+                "blockIO.blkioWeightDevice",
+                !block_io.blkio_weight_device.is_empty(),
+            ),
+            (
+                "blockIO.blkioThrottleReadBpsDevice",
+                !block_io.blkio_throttle_read_bps_device.is_empty(),
+            ),
+            (
+                "blockIO.blkioThrottleWriteBpsDevice",
+                !block_io.blkio_throttle_write_bps_device.is_empty(),
+            ),
+            (
+                "blockIO.blkioThrottleReadIOPSDevice",
+                !block_io.blkio_throttle_read_iops_device.is_empty(),
+            ),
+            (
+                "blockIO.blkioThrottleWriteIOPSDevice",
+                !block_io.blkio_throttle_write_iops_device.is_empty(),
+            ),
+        ];
+        ignored.extend(
+            fields
+                .iter()
+                .filter(|(_, set)| *set)
+                .map(|(field, _)| *field),
+        );
+    }
+    if !resources.hugepage_limits.is_empty() {
+        ignored.push("hugepageLimits");
+    }
+    if resources.network.is_some() {
+        ignored.push("network");
+    }
+    ignored
+}
+
+fn property(name: &str, value: Value) -> Value {
+    Value::Struct(vec![
+        Value::Str(name.to_string()),
+        Value::Variant(Box::new(value)),
+    ])
+}
+
+/// systemd spells unlimited as the largest value.
+fn infinity_or(value: i64) -> u64 {
+    if value < 0 {
+        u64::MAX
+    } else {
+        value as u64
+    }
+}
+
+fn manager_call(member: &str, body: Vec<Value>) -> Message {
+    Message::method_call(
+        SYSTEMD_DESTINATION,
+        SYSTEMD_PATH,
+        MANAGER_INTERFACE,
+        member,
+        body,
+    )
+}
+
+/// Asks systemd to send the signals about its jobs, which are only sent to
+/// subscribed clients.
+fn subscribe(connection: &mut Connection) -> Result<()> {
+    connection.add_match(&format!(
+        "type='signal',sender='{}',path='{}',interface='{}',member='JobRemoved'",
+        SYSTEMD_DESTINATION, SYSTEMD_PATH, MANAGER_INTERFACE
+    ))?;
+    connection.call(manager_call("Subscribe", Vec::new()))?;
+    Ok(())
+}
+
+/// Waits for the job whose path is in `reply` to be removed, which is when
+/// systemd is done with it, and checks that it succeeded.
+fn wait_for_job(connection: &mut Connection, reply: &[Value], unit: &str) -> Result<()> {
+    let job = match reply.first().and_then(Value::as_str) {
+        Some(job) => job.to_string(),
+        None => bail!("systemd returned no job for {}", unit),
+    };
+    let deadline = Instant::now() + JOB_TIMEOUT;
+    loop {
+        let message = match connection.read_message(Some(deadline)) {
+            Ok(message) => message,
+            Err(_) if Instant::now() >= deadline => {
+                bail!(
+                    "timed out waiting for systemd to finish {} of {}",
+                    job,
+                    unit
+                )
+            }
+            Err(e) => return Err(e),
+        };
+        if message.typ != SIGNAL || message.member.as_deref() != Some("JobRemoved") {
+            continue;
+        }
+        // JobRemoved carries the id, the path and the unit of the job and
+        // how it ended.
+        if message.body.get(1).and_then(Value::as_str) != Some(job.as_str()) {
+            continue;
+        }
+        return match message.body.get(3).and_then(Value::as_str) {
+            Some("done") => Ok(()),
+            Some(result) => bail!("systemd job for {} ended with {}", unit, result),
+            None => bail!("malformed JobRemoved signal for {}", unit),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(slice: &str, name: &str) -> Unit {
+        Unit {
+            slice: slice.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn parse_unit() {
+        assert_eq!(
+            Unit::parse("", "c1").unwrap(),
+            unit("system.slice", "youki-c1.scope")
+        );
+        assert_eq!(
+            Unit::parse("machine.slice:docker:abc", "c1").unwrap(),
+            unit("machine.slice", "docker-abc.scope")
+        );
+        assert_eq!(
+            Unit::parse("::", "c1").unwrap(),
+            unit("system.slice", "youki-c1.scope")
+        );
+        assert_eq!(
+            Unit::parse("user.slice::", "c1").unwrap(),
+            unit("user.slice", "youki-c1.scope")
+        );
+        // a slice as the name is used as the unit itself
+        assert_eq!(
+            Unit::parse("system.slice::pods-a.slice", "c1").unwrap(),
+            unit("system.slice", "pods-a.slice")
+        );
+    }
+
+    #[test]
+    fn parse_invalid_unit() {
+        for cgroups_path in &[
+            "/youki/c1",
+            "system.slice:youki",
+            "a:b:c:d",
+            "system:youki:c1",
+        ] {
+            assert!(
+                Unit::parse(cgroups_path, "c1").is_err(),
+                "{:?}",
+                cgroups_path
+            );
+        }
+    }
+
+    #[test]
+    fn slice_paths() {
+        assert_eq!(expand_slice("-.slice").unwrap(), PathBuf::from("/"));
+        assert_eq!(
+            expand_slice("system.slice").unwrap(),
+            PathBuf::from("/system.slice")
+        );
+        assert_eq!(
+            expand_slice("a-b-c.slice").unwrap(),
+            PathBuf::from("/a.slice/a-b.slice/a-b-c.slice")
+        );
+        assert_eq!(
+            unit("machine-pods.slice", "youki-c1.scope")
+                .cgroup_path()
+                .unwrap(),
+            PathBuf::from("/machine.slice/machine-pods.slice/youki-c1.scope")
+        );
+        for slice in &[".slice", "-a.slice", "a-.slice", "a--b.slice"] {
+            assert!(expand_slice(slice).is_err(), "{:?}", slice);
+        }
+    }
+
+    #[test]
+    fn resources_without_properties() {
+        let resources: LinuxResources = serde_json::from_str(
+            r#"{
+                "memory": {"limit": 1024, "swap": 2048},
+                "cpu": {"shares": 512, "cpus": "0-1"},
+                "pids": {"limit": 10},
+                "hugepageLimits": [{"pageSize": "2MB", "limit": 0}]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            ignored_resources(&resources),
+            vec!["memory.swap", "cpu.cpus", "hugepageLimits"]
+        );
+        assert!(ignored_resources(&LinuxResources::default()).is_empty());
+    }
+}
@@ -0,0 +1,97 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use clap::Parser;
+
+use crate::container::{Container, ContainerStatus};
+use crate::criu::{self, CriuOptions};
+use crate::spec::Spec;
+
+/// Checkpoint a running container into an image directory with CRIU
+#[derive(Debug, Parser)]
+pub struct Checkpoint {
+    pub container_id: String,
+    /// directory the images are written to
+    #[clap(long)]
+    pub image_path: PathBuf,
+    /// directory for the logs of CRIU, defaults to --image-path
+    #[clap(long)]
+    pub work_path: Option<PathBuf>,
+    /// keep the container running after it was checkpointed
+    #[clap(long)]
+    pub leave_running: bool,
+    /// directory of the images of a previous checkpoint, relative to
+    /// --image-path, to only dump what changed since
+    #[clap(long)]
+    pub parent_path: Option<PathBuf>,
+    /// create the namespace on restore without restoring its properties
+    #[clap(long, value_parser = ["network"])]
+    pub empty_ns: Vec<String>,
+    #[clap(flatten)]
+    pub criu_options: CriuOptions,
+}
+
+impl Checkpoint {
+    pub fn exec(&self, root_path: PathBuf) -> Result<()> {
+        let container_root = root_path.join(&self.container_id);
+        if !container_root.exists() {
+            bail!("container {} does not exist", self.container_id)
+        }
+        let mut container = Container::load(container_root)?;
+        container.refresh_status()?;
+        if !container.can_checkpoint() {
+            bail!(
+                "{} could not be checkpointed because it was {:?}",
+                container.id(),
+                container.status()
+            )
+        }
+        let pid = container.pid().unwrap();
+        let spec = Spec::load(Path::new(&container.state.bundle).join("config.json"))?;
+
+        fs::create_dir_all(&self.image_path)?;
+        let image_path = fs::canonicalize(&self.image_path)?;
+        let work_path = match &self.work_path {
+            Some(work_path) => {
+                fs::create_dir_all(work_path)?;
+                fs::canonicalize(work_path)?
+            }
+            None => image_path.clone(),
+        };
+
+        let mut args = vec![
+            "--tree".to_string(),
+            pid.to_string(),
+            "--images-dir".to_string(),
+            image_path.to_string_lossy().to_string(),
+        ];
+        args.extend(self.criu_options.args());
+        if self.leave_running {
+            args.push("--leave-running".to_string());
+        }
+        if let Some(parent_path) = &self.parent_path {
+            args.push("--prev-images-dir".to_string());
+            args.push(parent_path.to_string_lossy().to_string());
+        }
+        if !self.empty_ns.is_empty() {
+            args.push("--empty-ns".to_string());
+            args.push("net".to_string());
+        }
+        args.extend(criu::external_mounts(&spec, false));
+        args.extend(criu::external_namespaces(&spec, false)?.0);
+        args.extend(criu::save_descriptors(pid, &image_path)?);
+
+        criu::run(&self.criu_options.criu, "dump", &args, &work_path)?;
+
+        if !self.leave_running {
+            container.set_status(ContainerStatus::Stopped).save()?;
+        }
+        log::debug!(
+            "{} was checkpointed to {:?}.",
+            self.container_id,
+            image_path
+        );
+        Ok(())
+    }
+}
@@ -1,12 +1,16 @@
-use std::{fs, path::PathBuf};
+use std::{collections::HashMap, fs, path::{Path, PathBuf}};
 
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use nix::unistd::Pid;
 use nix::sys::signal::{self, Signal};
 use procfs::process::Process;
 
 use crate::container::{ContainerStatus, State};
 
+/// The namespaces of /proc/<pid>/ns recorded in the state.
+const NAMESPACES: &[&str] = &["cgroup", "ipc", "mnt", "net", "pid", "user", "uts"];
+
 #[derive(Debug)]
 pub struct Container {
     pub state: State,
@@ -43,28 +47,45 @@ impl Container {
     }
 
     pub fn refresh_status(&mut self) -> Result<()> {
-        let new_status = match self.pid() {
-            Some(pid) => {
-                if let Ok(proc) = Process::new(pid.as_raw()) {
-                    use procfs::process::ProcState;
-                    match proc.stat.state().unwrap() {
-                        ProcState::Zombie | ProcState::Dead => ContainerStatus::Stopped,
-                        _ => match self.status() {
-                            ContainerStatus::Creating | ContainerStatus::Created => self.status(),
-                            _ => ContainerStatus::Running,
-                        },
-                    }
-                } else {
-                    ContainerStatus::Stopped
+        let new_status = if self.is_init_alive() {
+            match self.status() {
+                ContainerStatus::Creating | ContainerStatus::Created | ContainerStatus::Paused => {
+                    self.status()
                 }
+                _ => ContainerStatus::Running,
             }
-            None => ContainerStatus::Stopped,
+        } else {
+            ContainerStatus::Stopped
         };
 
         self.set_status(new_status);
         Ok(())
     }
 
+    /// Returns true if the container process is still running, and is not
+    /// some later process that was given its pid, e.g. after a reboot.
+    pub fn is_init_alive(&self) -> bool {
+        match self.pid() {
+            Some(pid) => is_alive(pid, self.state.pid_start_time),
+            None => false,
+        }
+    }
+
+    /// Returns true if the container was left behind by a runtime that is
+    /// gone: either the create never finished, or the container process
+    /// ended without the runtime recording it, as happens on a reboot. Such
+    /// a container can't be started or stopped anymore, only deleted.
+    pub fn is_stale(&self) -> bool {
+        match self.status() {
+            ContainerStatus::Creating => match self.state.creator {
+                Some(creator) => !is_alive(Pid::from_raw(creator), self.state.creator_start_time),
+                None => true,
+            },
+            ContainerStatus::Stopped => false,
+            _ => !self.is_init_alive(),
+        }
+    }
+
     pub fn save(&self) -> Result<()> {
         self.state.save(&self.root)
     }
@@ -77,6 +98,22 @@ impl Container {
         self.state.status.can_kill()
     }
 
+    pub fn can_exec(&self) -> bool {
+        self.state.status.can_exec()
+    }
+
+    pub fn can_pause(&self) -> bool {
+        self.state.status.can_pause()
+    }
+
+    pub fn can_resume(&self) -> bool {
+        self.state.status.can_resume()
+    }
+
+    pub fn can_checkpoint(&self) -> bool {
+        self.state.status.can_checkpoint()
+    }
+
     pub fn can_delete(&self) -> bool {
         self.state.status.can_delete()
     }
@@ -85,15 +122,116 @@ impl Container {
         self.state.pid.map(Pid::from_raw)
     }
 
-    pub fn set_pid(&self, pid: i32) -> Self {
-        Self::new(
-            self.state.id.as_str(),
-            self.state.status,
-            Some(pid),
-            &self.state.bundle.as_str(),
-            &self.root,
-        )
-        .expect("unexpected error")
+    pub fn set_pid(&mut self, pid: i32) -> &mut Self {
+        self.state.pid = Some(pid);
+        self.state.pid_start_time = start_time(Pid::from_raw(pid));
+        self
+    }
+
+    /// Records `pid` as the runtime process creating the container.
+    pub fn set_creator(&mut self, pid: Pid) -> &mut Self {
+        self.state.creator = Some(pid.as_raw());
+        self.state.creator_start_time = start_time(pid);
+        self
+    }
+
+    pub fn cgroup_path(&self) -> Option<&Path> {
+        self.state.cgroup_path.as_deref()
+    }
+
+    pub fn set_cgroup_path(&mut self, cgroup_path: PathBuf) -> &mut Self {
+        self.state.cgroup_path = Some(cgroup_path);
+        self
+    }
+
+    pub fn systemd_unit(&self) -> Option<&str> {
+        self.state.systemd_unit.as_deref()
+    }
+
+    pub fn set_systemd_unit(&mut self, systemd_unit: String) -> &mut Self {
+        self.state.systemd_unit = Some(systemd_unit);
+        self
+    }
+
+    pub fn exit_code(&self) -> Option<i32> {
+        self.state.exit_code
+    }
+
+    pub fn set_exit_code(&mut self, exit_code: i32) -> &mut Self {
+        self.state.exit_code = Some(exit_code);
+        self
+    }
+
+    pub fn exit_signal(&self) -> Option<i32> {
+        self.state.exit_signal
+    }
+
+    pub fn set_exit_signal(&mut self, exit_signal: i32) -> &mut Self {
+        self.state.exit_signal = Some(exit_signal);
+        self
+    }
+
+    pub fn created_at(&self) -> Option<DateTime<Utc>> {
+        self.state.created_at
+    }
+
+    pub fn started_at(&self) -> Option<DateTime<Utc>> {
+        self.state.started_at
+    }
+
+    pub fn set_started_at(&mut self, started_at: DateTime<Utc>) -> &mut Self {
+        self.state.started_at = Some(started_at);
+        self
+    }
+
+    pub fn finished_at(&self) -> Option<DateTime<Utc>> {
+        self.state.finished_at
+    }
+
+    pub fn set_finished_at(&mut self, finished_at: DateTime<Utc>) -> &mut Self {
+        self.state.finished_at = Some(finished_at);
+        self
+    }
+
+    pub fn annotations(&self) -> &HashMap<String, String> {
+        &self.state.annotations
+    }
+
+    pub fn set_annotations(&mut self, annotations: HashMap<String, String>) -> &mut Self {
+        self.state.annotations = annotations;
+        self
+    }
+
+    pub fn rootfs(&self) -> Option<&Path> {
+        self.state.rootfs.as_deref()
+    }
+
+    pub fn set_rootfs(&mut self, rootfs: PathBuf) -> &mut Self {
+        self.state.rootfs = Some(rootfs);
+        self
+    }
+
+    pub fn owner(&self) -> Option<u32> {
+        self.state.owner
+    }
+
+    pub fn is_rootless(&self) -> bool {
+        self.state.rootless
+    }
+
+    pub fn namespace_paths(&self) -> &HashMap<String, PathBuf> {
+        &self.state.namespace_paths
+    }
+
+    /// Records the namespaces of the container process `pid`, as found in
+    /// /proc.
+    pub fn set_namespace_paths(&mut self, pid: Pid) -> &mut Self {
+        self.state.namespace_paths = NAMESPACES
+            .iter()
+            .map(|name| (name.to_string(), PathBuf::from(format!("/proc/{}/ns/{}", pid, name))))
+            .filter(|(_, path)| path.exists())
+            .collect();
+        self
     }
 
     pub fn load(container_root: PathBuf) -> Result<Self> {
@@ -109,3 +247,25 @@ impl Container {
         Ok(())
     }
 }
+
+/// Returns when `pid` started, in clock ticks after boot.
+fn start_time(pid: Pid) -> Option<u64> {
+    Process::new(pid.as_raw()).ok().map(|p| p.stat.starttime)
+}
+
+/// Returns true if `pid` is running and, when `start_time` is known, is
+/// still the process that started then. States written before the start
+/// time was recorded only have the pid to go by.
+fn is_alive(pid: Pid, start_time: Option<u64>) -> bool {
+    use procfs::process::ProcState;
+    match Process::new(pid.as_raw()) {
+        Ok(proc) => {
+            let running = !matches!(
+                proc.stat.state(),
+                Ok(ProcState::Zombie) | Ok(ProcState::Dead)
+            );
+            running && start_time.is_none_or(|t| t == proc.stat.starttime)
+        }
+        Err(_) => false,
+    }
+}
@@ -1,15 +1,27 @@
 use std::{
     collections::HashMap,
+    fmt,
     fs::{self, File},
     path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use nix::unistd;
 use serde::{Deserialize, Serialize};
 
+use crate::rootless;
+
 const STATE_FILE_PATH: &str = "state.json";
+/// The version of the layout of state.json. It is bumped whenever a change
+/// can't be read by older versions; fields that are only added get a default
+/// instead, so that older files keep loading.
+const SCHEMA_VERSION: u32 = 1;
+/// Tells apart the temporary files of the saves made by this process.
+static TMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
-#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub enum ContainerStatus {
     // StateCreating indicates that the container is being created
@@ -21,6 +33,8 @@ pub enum ContainerStatus {
     Running,
     // StateStopped indicates that the container process has exited
     Stopped,
+    // StatePaused indicates that the container's processes are frozen
+    Paused,
 }
 
 impl ContainerStatus {
@@ -32,18 +46,55 @@ impl ContainerStatus {
         use ContainerStatus::*;
         match self {
             Creating | Stopped => false,
+            Created | Running | Paused => true,
+        }
+    }
+
+    pub fn can_exec(&self) -> bool {
+        use ContainerStatus::*;
+        match self {
+            Creating | Stopped | Paused => false,
             Created | Running => true,
         }
     }
 
+    pub fn can_pause(&self) -> bool {
+        matches!(self, ContainerStatus::Running)
+    }
+
+    pub fn can_resume(&self) -> bool {
+        matches!(self, ContainerStatus::Paused)
+    }
+
+    pub fn can_checkpoint(&self) -> bool {
+        matches!(self, ContainerStatus::Running)
+    }
+
     pub fn can_delete(&self) -> bool {
         matches!(self, ContainerStatus::Stopped)
     }
 }
 
+impl fmt::Display for ContainerStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let print = match *self {
+            Self::Creating => "creating",
+            Self::Created => "created",
+            Self::Running => "running",
+            Self::Stopped => "stopped",
+            Self::Paused => "paused",
+        };
+        write!(f, "{}", print)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct State {
+    // SchemaVersion is the version of the layout of this file. Files written
+    // before it was introduced are version 0.
+    #[serde(default)]
+    pub schema_version: u32,
     // Version is the version of the specification that is supported.
     pub oci_version: String,
     // ID is the container ID
@@ -52,10 +103,60 @@ pub struct State {
     pub status: ContainerStatus,
     // Pid is the process ID for the container process.
     pub pid: Option<i32>,
+    // PidStartTime is when the container process started, in clock ticks
+    // after boot, telling it apart from a later process reusing its pid.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid_start_time: Option<u64>,
     // Bundle is the path to the container's bundle directory.
     pub bundle: String,
     // Annotations are key values associated with the container.
     pub annotations: HashMap<String, String>,
+    // CgroupPath is the path of the cgroup the runtime created for the
+    // container, relative to the root of each hierarchy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cgroup_path: Option<PathBuf>,
+    // SystemdUnit is the transient unit holding the cgroup when it is
+    // managed by systemd.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub systemd_unit: Option<String>,
+    // ExitCode is the exit code of the container process once it has exited,
+    // 128 plus the signal number if it was killed by a signal.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    // ExitSignal is the signal that killed the container process, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_signal: Option<i32>,
+    // CreatedAt is when the container was created.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    // StartedAt is when the user-specified program was started.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<DateTime<Utc>>,
+    // FinishedAt is when the container process was seen exiting.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    // Rootfs is the absolute path of the root filesystem of the container.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rootfs: Option<PathBuf>,
+    // Owner is the uid of the user who created the container.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<u32>,
+    // Creator is the runtime process creating the container, which is at
+    // work for as long as the status is creating.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub creator: Option<i32>,
+    // CreatorStartTime is when the creator started, in clock ticks after
+    // boot.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub creator_start_time: Option<u64>,
+    // Rootless is true if the container was created in rootless mode, which
+    // --rootless sets and which defaults to whether the user is unprivileged.
+    #[serde(default)]
+    pub rootless: bool,
+    // NamespacePaths are the paths of the namespaces of the container
+    // process, keyed by their name in /proc/<pid>/ns.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub namespace_paths: HashMap<String, PathBuf>,
 }
 
 impl State {
@@ -65,34 +166,72 @@ impl State {
         pid: Option<i32>,
         bundle: &str,
     ) -> Self {
+        let owner = unistd::geteuid();
         Self {
+            schema_version: SCHEMA_VERSION,
             oci_version: "v1.0.0".to_string(),
             id: container_id.to_string(),
             status,
             pid,
+            pid_start_time: None,
             bundle: bundle.to_string(),
             annotations: HashMap::default(),
+            cgroup_path: None,
+            systemd_unit: None,
+            exit_code: None,
+            exit_signal: None,
+            created_at: Some(Utc::now()),
+            started_at: None,
+            finished_at: None,
+            rootfs: None,
+            owner: Some(owner.as_raw()),
+            creator: None,
+            creator_start_time: None,
+            rootless: rootless::is_rootless(),
+            namespace_paths: HashMap::new(),
         }
     }
 
     pub fn save(&self, container_root: &PathBuf) -> Result<()> {
-        let state_file_path = container_root.join(STATE_FILE_PATH);
+        // The state is written aside and renamed over the old one, so that
+        // the processes of the container reading it concurrently never see
+        // it half written. Each write gets its own file, as the exit of the
+        // container may be recorded while a command saves the state too.
+        let tmp_file_path = container_root.join(format!(
+            ".{}.{}.{}.tmp",
+            STATE_FILE_PATH,
+            unistd::getpid(),
+            TMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
         let file = fs::OpenOptions::new()
-            .read(true)
             .write(true)
-            .append(false)
-            .create(true)
-            .truncate(true)
-            .open(state_file_path)
-            .expect("Unable to open");
-        serde_json::to_writer(&file, self)?;
+            .create_new(true)
+            .open(&tmp_file_path)?;
+        if let Err(e) = serde_json::to_writer(&file, self) {
+            let _ = fs::remove_file(&tmp_file_path);
+            return Err(e.into());
+        }
+        if let Err(e) = fs::rename(&tmp_file_path, container_root.join(STATE_FILE_PATH)) {
+            let _ = fs::remove_file(&tmp_file_path);
+            return Err(e.into());
+        }
         Ok(())
     }
 
     pub fn load(container_root: &PathBuf) -> Result<Self> {
         let state_file_path = container_root.join(STATE_FILE_PATH);
-        let file = File::open(state_file_path)?;
-        let state: Self = serde_json::from_reader(&file)?;
+        let file = File::open(&state_file_path)?;
+        let mut state: Self = serde_json::from_reader(&file)?;
+        if state.schema_version > SCHEMA_VERSION {
+            bail!(
+                "{:?} has schema version {}, newer than the supported {}",
+                state_file_path,
+                state.schema_version,
+                SCHEMA_VERSION
+            );
+        }
+        // Older layouts only lack fields, which have been defaulted.
+        state.schema_version = SCHEMA_VERSION;
         Ok(state)
     }
 }
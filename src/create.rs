@@ -1,3 +1,4 @@
+use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process;
@@ -10,28 +11,59 @@ use nix::sys::stat;
 use nix::unistd;
 use nix::unistd::{sethostname, Gid, Uid};
 
+use crate::cgroups::systemd;
 use crate::container::{Container, ContainerStatus};
 use crate::notify_socket::NotifyListener;
 use crate::process::{fork, Process};
-use crate::{rootfs, capabilities};
+use crate::rootfs;
+use crate::rootless;
 use crate::spec;
-use crate::stdio::FileDescriptor;
+use crate::stdio::{self, FileDescriptor};
 use crate::tty;
 use crate::utils;
 
 #[derive(Parser, Debug)]
 pub struct Create {
+    /// file to write the PID of the container's process to
     #[clap(short, long)]
-    pid_file: Option<String>,
+    pid_file: Option<PathBuf>,
     #[clap(short, long, default_value = ".")]
     bundle: PathBuf,
     #[clap(short, long)]
     console_socket: Option<String>,
     pub container_id: String,
+    /// move the rootfs over / and chroot into it instead of using
+    /// pivot_root(2), for a rootfs on ramfs. This is less secure: the host's
+    /// root stays mounted and can be reached again by escaping the chroot,
+    /// e.g. with CAP_SYS_CHROOT
+    #[clap(long)]
+    no_pivot: bool,
+    /// pass N more file descriptors to the container's process, from fd 3
+    /// on, after any passed for socket activation
+    #[clap(long, default_value = "0")]
+    preserve_fds: usize,
+    /// accepted for runc compatibility, the container always keeps the
+    /// session keyring of the runtime
+    #[clap(long)]
+    no_new_keyring: bool,
+    /// set from the global --systemd-cgroup flag
+    #[clap(skip)]
+    pub systemd_cgroup: bool,
 }
 
 impl Create {
     pub fn exec(&self, root_path: PathBuf) -> Result<()> {
+        if let Process::Parent(_) = self.create(root_path)? {
+            log::debug!("{} was successfully created", self.container_id);
+            process::exit(0);
+        }
+        Ok(())
+    }
+
+    /// Create the container and return the role of the current process
+    /// after the forks, so that callers like `run` can keep driving the
+    /// container from the parent.
+    pub fn create(&self, root_path: PathBuf) -> Result<Process> {
         log::debug!("{} is being created...", self.container_id);
         let container_dir = root_path.join(&self.container_id);
         if !container_dir.exists() {
@@ -40,9 +72,19 @@ impl Create {
             bail!("{} already exists", self.container_id);
         }
 
-        unistd::chdir(&self.bundle)?;
+        // relative to where create was run, before it moves into the bundle
+        let pid_file = match &self.pid_file {
+            Some(pid_file) => Some(env::current_dir()?.join(pid_file)),
+            None => None,
+        };
+
+        let bundle = fs::canonicalize(&self.bundle)?;
+        unistd::chdir(&bundle)?;
 
         let spec = spec::Spec::load("config.json")?;
+        if rootless::is_rootless() {
+            rootless::validate(&spec)?;
+        }
 
         let container_dir = fs::canonicalize(container_dir)?;
         unistd::chdir(&*container_dir)?;
@@ -51,15 +93,23 @@ impl Create {
             &self.container_id,
             ContainerStatus::Creating,
             None,
-            self.bundle.to_str().unwrap(),
+            bundle.to_str().unwrap(),
             &container_dir,
         )?;
+        let rootfs = fs::canonicalize(&spec.root.path)?;
+        container
+            .set_annotations(spec.annotations.clone())
+            .set_rootfs(rootfs.clone())
+            .set_creator(unistd::getpid());
+        if self.systemd_cgroup {
+            let linux = spec.linux.as_ref().unwrap();
+            let unit = systemd::Unit::parse(&linux.cgroups_path, &self.container_id)?;
+            container.set_systemd_unit(unit.name);
+        }
         container.save()?;
 
         let mut notify_socket: NotifyListener = NotifyListener::new(&container_dir)?;
 
-        let rootfs = fs::canonicalize(&spec.root.path)?;
-
         let (csocketfd, _consolefd) = {
             if let Some(console_socket) = &self.console_socket {
                 let (csocketfd, consolefd) =
@@ -70,25 +120,22 @@ impl Create {
             }
         };
 
-        let process = run_container(
-            self.pid_file.as_ref(),
+        run_container(
+            self,
+            pid_file.as_deref(),
             &mut notify_socket,
             &rootfs,
             &spec,
             csocketfd,
             &mut container,
-        )?;
-        if let Process::Parent(_) = process {
-            log::debug!("{} was successfully created", self.container_id);
-            process::exit(0);
-        }
-        Ok(())
+        )
     }
 
 }
 
-fn run_container<P: AsRef<Path>>(
-    pid_file: Option<P>,
+fn run_container(
+    create: &Create,
+    pid_file: Option<&Path>,
     notify_socket: &mut NotifyListener,
     rootfs: &PathBuf,
     spec: &spec::Spec,
@@ -96,6 +143,10 @@ fn run_container<P: AsRef<Path>>(
     container: &mut Container,
 ) -> Result<Process>{
     prctl::set_dumpable(false).unwrap();
+    // The sockets systemd passed for activation come first, followed by
+    // the descriptors to preserve.
+    let listen_fds = stdio::listen_fds();
+    let extra_fds = listen_fds + create.preserve_fds;
     let linux = spec.linux.as_ref().unwrap();
 
     let mut cf = sched::CloneFlags::empty();
@@ -118,7 +169,7 @@ fn run_container<P: AsRef<Path>>(
     )? {
         Process::Parent(parent) => Ok(Process::Parent(parent)),
         Process::Child(child) => {
-            setid(Uid::from_raw(0), Gid::from_raw(0))?;
+            utils::setid(Uid::from_raw(0), Gid::from_raw(0))?;
             if let Some(csocketfd) = csocketfd {
                 tty::ready(csocketfd)?;
             }
@@ -132,7 +183,7 @@ fn run_container<P: AsRef<Path>>(
 			 * which would break many applications and libraries, so we must fork
 			 * to actually enter the new PID namespace.
 			 */
-            match fork::fork_init(child)? {
+            match fork::fork_init(child, Some(&container.root))? {
                 Process::Child(child) => Ok(Process::Child(child)),
                 Process::Init(mut init) => {
                     // join namepsaces
@@ -140,7 +191,7 @@ fn run_container<P: AsRef<Path>>(
                         sched::setns(fd, space)?;
                         unistd::close(fd)?;
                         if space == sched::CloneFlags::CLONE_NEWUSER {
-                            setid(Uid::from_raw(0), Gid::from_raw(0))?;
+                            utils::setid(Uid::from_raw(0), Gid::from_raw(0))?;
                         }
                     }
 
@@ -153,37 +204,27 @@ fn run_container<P: AsRef<Path>>(
                         rootfs,
                         cf.contains(sched::CloneFlags::CLONE_NEWUSER),
                     ))?;
-                    rootfs::pivot_rootfs(&*rootfs)?;
+                    if create.no_pivot {
+                        rootfs::move_rootfs(rootfs)?;
+                    } else {
+                        rootfs::pivot_rootfs(&*rootfs)?;
+                    }
 
                     init.ready()?;
 
                     notify_socket.wait_for_container_start()?;
-                    if spec.process.no_new_privileges {
-                        let _ = prctl::set_no_new_privileges(true);
-                    }
 
-                    // set hostname and environment value
                     sethostname(&spec.hostname)?;
-                    utils::set_env_val(&spec.process.env);
-
-                    setid(
-                        Uid::from_raw(spec.process.user.uid),
-                        Gid::from_raw(spec.process.user.gid)
-                    )?;
-                    
-                    capabilities::reset_effective()?;
-                    if let Some(caps) = &spec.process.capabilities {
-                        capabilities::set_capabilities(&caps)?;
-                    }
-
-                    // set rlimits
-                    for rlimit in &spec.process.rlimits {
-                        utils::set_rlimits(rlimit)?;
+                    utils::set_process(&spec.process)?;
+                    if listen_fds > 0 {
+                        stdio::set_listen_env(listen_fds);
                     }
+                    stdio::close_fds_on_exec(extra_fds)?;
 
+                    // do_exec only returns on failure. Once the program has
+                    // exited, the intermediate process waiting in fork_init
+                    // records it in the state.
                     utils::do_exec(&spec.process.args[0], &spec.process.args)?;
-                    container.set_status(ContainerStatus::Stopped);
-                    container.save()?;
 
                     Ok(Process::Init(init))
                 }
@@ -193,19 +234,3 @@ fn run_container<P: AsRef<Path>>(
         _ => unreachable!(),
     }
 }
-
-fn setid(uid: Uid, gid: Gid) -> Result<()> {
-    if let Err(e) = prctl::set_keep_capabilities(true) {
-        bail!("set keep capabilities returned {}", e);
-    };
-
-    unistd::setresgid(gid, gid, gid)?;
-    unistd::setresuid(uid, uid, uid)?;
-    if uid != Uid::from_raw(0) {
-        capabilities::reset_effective()?;
-    }
-    if let Err(e) = prctl::set_keep_capabilities(false) {
-        bail!("set keep capabilities returned {}", e);
-    };
-    Ok(())
-}
@@ -0,0 +1,196 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use nix::fcntl::{self, OFlag};
+use nix::sys::stat::Mode;
+use nix::unistd::Pid;
+
+use crate::spec::{LinuxNamespaceType, Spec};
+
+/// The file in the image directory recording what the standard streams of
+/// the container's process were connected to.
+const DESCRIPTORS: &str = "descriptors.json";
+
+/// The options of CRIU shared by `checkpoint` and `restore`, named after
+/// those of runc.
+#[derive(Debug, Parser)]
+pub struct CriuOptions {
+    /// checkpoint or restore established TCP connections
+    #[clap(long)]
+    pub tcp_established: bool,
+    /// allow external unix sockets
+    #[clap(long)]
+    pub ext_unix_sk: bool,
+    /// allow a process attached to the terminal of a shell
+    #[clap(long)]
+    pub shell_job: bool,
+    /// handle file locks
+    #[clap(long)]
+    pub file_locks: bool,
+    /// how the cgroups of the container are handled
+    #[clap(long, value_parser = ["soft", "full", "strict", "ignore"])]
+    pub manage_cgroups_mode: Option<String>,
+    /// set from the global --criu flag
+    #[clap(skip)]
+    pub criu: PathBuf,
+}
+
+impl CriuOptions {
+    /// Returns the arguments of CRIU for these options.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        let flags = [
+            (self.tcp_established, "--tcp-established"),
+            (self.ext_unix_sk, "--ext-unix-sk"),
+            (self.shell_job, "--shell-job"),
+            (self.file_locks, "--file-locks"),
+        ];
+        for (set, flag) in flags.iter() {
+            if *set {
+                args.push(flag.to_string());
+            }
+        }
+        match &self.manage_cgroups_mode {
+            Some(mode) => {
+                args.push("--manage-cgroups-mode".to_string());
+                args.push(mode.clone());
+            }
+            None => args.push("--manage-cgroups".to_string()),
+        }
+        args
+    }
+}
+
+/// Runs `<criu> <action>` with `args`, logging into `work_path`.
+pub fn run(criu: &Path, action: &str, args: &[String], work_path: &Path) -> Result<()> {
+    let log_file = format!("{}.log", action);
+    log::debug!("{:?} {} {:?}", criu, action, args);
+    let status = match Command::new(criu)
+        .arg(action)
+        .args(args)
+        .arg("--work-dir")
+        .arg(work_path)
+        .arg("--log-file")
+        .arg(&log_file)
+        .arg("-v4")
+        .status()
+    {
+        Ok(status) => status,
+        Err(e) if e.kind() == ErrorKind::NotFound => bail!("{:?} was not found", criu),
+        Err(e) => bail!("failed to run {:?}: {}", criu, e),
+    };
+    if !status.success() {
+        bail!(
+            "{:?} {} failed with {}, see {:?} for details",
+            criu,
+            action,
+            status,
+            work_path.join(log_file)
+        );
+    }
+    Ok(())
+}
+
+/// Returns the arguments declaring the bind mounts of the spec as external,
+/// since CRIU can't dump or restore what lies outside of the container.
+/// They are keyed by their destination, which is mapped back to the source
+/// on restore.
+pub fn external_mounts(spec: &Spec, restore: bool) -> Vec<String> {
+    let mut args = Vec::new();
+    for m in &spec.mounts {
+        if !m.options.iter().any(|o| o == "bind" || o == "rbind") {
+            continue;
+        }
+        let target = if restore { &m.source } else { &m.destination };
+        args.push("--ext-mount-map".to_string());
+        args.push(format!("{}:{}", m.destination.display(), target.display()));
+    }
+    args
+}
+
+/// Returns the arguments declaring the namespaces that the container joined
+/// rather than created as external. On restore, they are opened and their
+/// descriptors, which have to stay open until CRIU exits, are returned too.
+pub fn external_namespaces(spec: &Spec, restore: bool) -> Result<(Vec<String>, Vec<RawFd>)> {
+    let mut args = Vec::new();
+    let mut fds = Vec::new();
+    let namespaces = spec
+        .linux
+        .as_ref()
+        .map(|l| l.namespaces.as_slice())
+        .unwrap_or(&[]);
+    for ns in namespaces.iter().filter(|ns| !ns.path.is_empty()) {
+        let (kind, key) = match ns.typ {
+            LinuxNamespaceType::Network => ("net", "extRootNetNS"),
+            LinuxNamespaceType::Pid => ("pid", "extRootPidNS"),
+            typ => bail!(
+                "checkpointing a joined {:?} namespace is not supported",
+                typ
+            ),
+        };
+        if restore {
+            let fd = fcntl::open(&*ns.path, OFlag::O_RDONLY, Mode::empty())?;
+            fds.push(fd);
+            args.push("--inherit-fd".to_string());
+            args.push(format!("fd[{}]:{}", fd, key));
+        } else {
+            let inode = fs::metadata(&ns.path)?.ino();
+            args.push("--external".to_string());
+            args.push(format!("{}[{}]:{}", kind, inode, key));
+        }
+    }
+    Ok((args, fds))
+}
+
+/// Records the standard streams of `pid` into the image directory and
+/// returns the arguments declaring its terminal, if it has one, as external.
+/// A terminal is recorded by its CRIU key, anything else by the target of
+/// its link in /proc.
+pub fn save_descriptors(pid: Pid, image_path: &Path) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    let mut descriptors = Vec::new();
+    for fd in 0..3 {
+        let path = PathBuf::from(format!("/proc/{}/fd/{}", pid, fd));
+        let target = fs::read_link(&path)?.to_string_lossy().to_string();
+        if target.starts_with("/dev/pts/") {
+            let metadata = fs::metadata(&path)?;
+            let key = format!("tty[{:x}:{:x}]", metadata.rdev(), metadata.dev());
+            if !args.contains(&key) {
+                args.push("--external".to_string());
+                args.push(key.clone());
+            }
+            descriptors.push(key);
+        } else {
+            descriptors.push(target);
+        }
+    }
+    fs::write(
+        image_path.join(DESCRIPTORS),
+        serde_json::to_string(&descriptors)?,
+    )?;
+    Ok(args)
+}
+
+/// Reads the standard streams recorded by `save_descriptors`.
+pub fn load_descriptors(image_path: &Path) -> Result<Vec<String>> {
+    let path = image_path.join(DESCRIPTORS);
+    let file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(e) => bail!(
+            "failed to open {:?}, is {:?} a checkpoint image? {}",
+            path,
+            image_path,
+            e
+        ),
+    };
+    match serde_json::from_reader(file) {
+        Ok(descriptors) => Ok(descriptors),
+        Err(e) => bail!("failed to parse {:?}: {}", path, e),
+    }
+}
@@ -0,0 +1,828 @@
+//! A minimal D-Bus client, implementing just what talking to systemd needs:
+//! authentication over a Unix socket, method calls and signals.
+
+use std::collections::VecDeque;
+use std::env;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use nix::unistd;
+
+const DEFAULT_SYSTEM_BUS_ADDRESS: &str = "unix:path=/run/dbus/system_bus_socket";
+const BUS_DESTINATION: &str = "org.freedesktop.DBus";
+const BUS_PATH: &str = "/org/freedesktop/DBus";
+const BUS_INTERFACE: &str = "org.freedesktop.DBus";
+
+pub const METHOD_CALL: u8 = 1;
+pub const METHOD_RETURN: u8 = 2;
+pub const ERROR: u8 = 3;
+pub const SIGNAL: u8 = 4;
+
+const FIELD_PATH: u8 = 1;
+const FIELD_INTERFACE: u8 = 2;
+const FIELD_MEMBER: u8 = 3;
+const FIELD_ERROR_NAME: u8 = 4;
+const FIELD_REPLY_SERIAL: u8 = 5;
+const FIELD_DESTINATION: u8 = 6;
+const FIELD_SENDER: u8 = 7;
+const FIELD_SIGNATURE: u8 = 8;
+
+/// A value of the D-Bus type system.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Byte(u8),
+    Bool(bool),
+    Int16(i16),
+    UInt16(u16),
+    Int32(i32),
+    UInt32(u32),
+    Int64(i64),
+    UInt64(u64),
+    Double(f64),
+    Str(String),
+    ObjectPath(String),
+    Signature(String),
+    /// The signature of the elements, which an empty array still needs,
+    /// and the elements.
+    Array(String, Vec<Value>),
+    Struct(Vec<Value>),
+    DictEntry(Box<Value>, Box<Value>),
+    Variant(Box<Value>),
+}
+
+impl Value {
+    pub fn signature(&self) -> String {
+        match self {
+            Value::Byte(_) => "y".to_string(),
+            Value::Bool(_) => "b".to_string(),
+            Value::Int16(_) => "n".to_string(),
+            Value::UInt16(_) => "q".to_string(),
+            Value::Int32(_) => "i".to_string(),
+            Value::UInt32(_) => "u".to_string(),
+            Value::Int64(_) => "x".to_string(),
+            Value::UInt64(_) => "t".to_string(),
+            Value::Double(_) => "d".to_string(),
+            Value::Str(_) => "s".to_string(),
+            Value::ObjectPath(_) => "o".to_string(),
+            Value::Signature(_) => "g".to_string(),
+            Value::Array(element, _) => format!("a{}", element),
+            Value::Struct(fields) => {
+                let fields: String = fields.iter().map(Value::signature).collect();
+                format!("({})", fields)
+            }
+            Value::DictEntry(key, value) => {
+                format!("{{{}{}}}", key.signature(), value.signature())
+            }
+            Value::Variant(_) => "v".to_string(),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) | Value::ObjectPath(s) | Value::Signature(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            Value::UInt32(u) => Some(*u),
+            _ => None,
+        }
+    }
+}
+
+/// Returns how values of the type starting `signature` are aligned.
+fn alignment(signature: &str) -> usize {
+    match signature.as_bytes().first() {
+        Some(b'n') | Some(b'q') => 2,
+        Some(b'b') | Some(b'i') | Some(b'u') | Some(b's') | Some(b'o') | Some(b'a') => 4,
+        Some(b'x') | Some(b't') | Some(b'd') | Some(b'(') | Some(b'{') => 8,
+        _ => 1,
+    }
+}
+
+/// Returns the length of the single complete type starting `signature`.
+fn single_type_len(signature: &str) -> Result<usize> {
+    let bytes = signature.as_bytes();
+    match bytes.first() {
+        Some(b'a') => Ok(1 + single_type_len(&signature[1..])?),
+        Some(&open) if open == b'(' || open == b'{' => {
+            let close = if open == b'(' { b')' } else { b'}' };
+            let mut len = 1;
+            while bytes.get(len) != Some(&close) {
+                if len >= bytes.len() {
+                    bail!("unterminated container in signature {:?}", signature);
+                }
+                len += single_type_len(&signature[len..])?;
+            }
+            Ok(len + 1)
+        }
+        Some(b'y') | Some(b'b') | Some(b'n') | Some(b'q') | Some(b'i') | Some(b'u')
+        | Some(b'x') | Some(b't') | Some(b'd') | Some(b's') | Some(b'o') | Some(b'g')
+        | Some(b'v') => Ok(1),
+        _ => bail!("unsupported signature {:?}", signature),
+    }
+}
+
+/// Splits `signature` into its complete types.
+fn split_signature(mut signature: &str) -> Result<Vec<&str>> {
+    let mut types = Vec::new();
+    while !signature.is_empty() {
+        let len = single_type_len(signature)?;
+        types.push(&signature[..len]);
+        signature = &signature[len..];
+    }
+    Ok(types)
+}
+
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn pad(&mut self, align: usize) {
+        while !self.buf.len().is_multiple_of(align) {
+            self.buf.push(0);
+        }
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.pad(4);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn write_signature(&mut self, signature: &str) {
+        self.buf.push(signature.len() as u8);
+        self.buf.extend_from_slice(signature.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn write(&mut self, value: &Value) {
+        match value {
+            Value::Byte(b) => self.buf.push(*b),
+            Value::Bool(b) => self.write_u32(*b as u32),
+            Value::Int16(n) => {
+                self.pad(2);
+                self.buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::UInt16(n) => {
+                self.pad(2);
+                self.buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Int32(n) => {
+                self.pad(4);
+                self.buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::UInt32(n) => self.write_u32(*n),
+            Value::Int64(n) => {
+                self.pad(8);
+                self.buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::UInt64(n) => {
+                self.pad(8);
+                self.buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Double(n) => {
+                self.pad(8);
+                self.buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Str(s) | Value::ObjectPath(s) => {
+                self.write_u32(s.len() as u32);
+                self.buf.extend_from_slice(s.as_bytes());
+                self.buf.push(0);
+            }
+            Value::Signature(s) => self.write_signature(s),
+            Value::Array(element, values) => {
+                self.write_u32(0);
+                let len_at = self.buf.len() - 4;
+                // the padding to the first element isn't part of the length
+                self.pad(alignment(element));
+                let start = self.buf.len();
+                for value in values {
+                    self.write(value);
+                }
+                let len = (self.buf.len() - start) as u32;
+                self.buf[len_at..len_at + 4].copy_from_slice(&len.to_le_bytes());
+            }
+            Value::Struct(fields) => {
+                self.pad(8);
+                for field in fields {
+                    self.write(field);
+                }
+            }
+            Value::DictEntry(key, value) => {
+                self.pad(8);
+                self.write(key);
+                self.write(value);
+            }
+            Value::Variant(value) => {
+                self.write_signature(&value.signature());
+                self.write(value);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn align(&mut self, align: usize) {
+        self.pos = self.pos.div_ceil(align) * align;
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.pos + len > self.buf.len() {
+            bail!("truncated D-Bus message");
+        }
+        let bytes = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn read_fixed<const N: usize>(&mut self) -> Result<[u8; N]> {
+        self.align(N);
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(self.take(N)?);
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.read_fixed()?))
+    }
+
+    fn read_string(&mut self, len: usize) -> Result<String> {
+        let s = String::from_utf8(self.take(len)?.to_vec())?;
+        self.take(1)?;
+        Ok(s)
+    }
+
+    fn read_signature(&mut self) -> Result<String> {
+        let len = self.take(1)?[0] as usize;
+        self.read_string(len)
+    }
+
+    /// Reads a value of the single complete type `signature`.
+    fn read(&mut self, signature: &str) -> Result<Value> {
+        let value = match signature.as_bytes()[0] {
+            b'y' => Value::Byte(self.take(1)?[0]),
+            b'b' => Value::Bool(self.read_u32()? != 0),
+            b'n' => Value::Int16(i16::from_le_bytes(self.read_fixed()?)),
+            b'q' => Value::UInt16(u16::from_le_bytes(self.read_fixed()?)),
+            b'i' => Value::Int32(i32::from_le_bytes(self.read_fixed()?)),
+            b'u' => Value::UInt32(self.read_u32()?),
+            b'x' => Value::Int64(i64::from_le_bytes(self.read_fixed()?)),
+            b't' => Value::UInt64(u64::from_le_bytes(self.read_fixed()?)),
+            b'd' => Value::Double(f64::from_le_bytes(self.read_fixed()?)),
+            b's' => {
+                let len = self.read_u32()? as usize;
+                Value::Str(self.read_string(len)?)
+            }
+            b'o' => {
+                let len = self.read_u32()? as usize;
+                Value::ObjectPath(self.read_string(len)?)
+            }
+            b'g' => Value::Signature(self.read_signature()?),
+            b'a' => {
+                let element = &signature[1..];
+                let len = self.read_u32()? as usize;
+                self.align(alignment(element));
+                let end = self.pos + len;
+                let mut values = Vec::new();
+                while self.pos < end {
+                    values.push(self.read(element)?);
+                }
+                Value::Array(element.to_string(), values)
+            }
+            b'(' => {
+                self.align(8);
+                let fields = split_signature(&signature[1..signature.len() - 1])?
+                    .into_iter()
+                    .map(|field| self.read(field))
+                    .collect::<Result<_>>()?;
+                Value::Struct(fields)
+            }
+            b'{' => {
+                self.align(8);
+                let types = split_signature(&signature[1..signature.len() - 1])?;
+                if types.len() != 2 {
+                    bail!("invalid dict entry signature {:?}", signature);
+                }
+                let key = self.read(types[0])?;
+                let value = self.read(types[1])?;
+                Value::DictEntry(Box::new(key), Box::new(value))
+            }
+            b'v' => {
+                let signature = self.read_signature()?;
+                if single_type_len(&signature)? != signature.len() {
+                    bail!("invalid variant signature {:?}", signature);
+                }
+                Value::Variant(Box::new(self.read(&signature)?))
+            }
+            _ => bail!("unsupported signature {:?}", signature),
+        };
+        Ok(value)
+    }
+}
+
+/// A D-Bus message. The serial is set by the connection sending it.
+#[derive(Debug, Clone, Default)]
+pub struct Message {
+    pub typ: u8,
+    pub flags: u8,
+    pub serial: u32,
+    pub path: Option<String>,
+    pub interface: Option<String>,
+    pub member: Option<String>,
+    pub error_name: Option<String>,
+    pub reply_serial: Option<u32>,
+    pub destination: Option<String>,
+    pub sender: Option<String>,
+    pub body: Vec<Value>,
+}
+
+impl Message {
+    pub fn method_call(
+        destination: &str,
+        path: &str,
+        interface: &str,
+        member: &str,
+        body: Vec<Value>,
+    ) -> Self {
+        Self {
+            typ: METHOD_CALL,
+            destination: Some(destination.to_string()),
+            path: Some(path.to_string()),
+            interface: Some(interface.to_string()),
+            member: Some(member.to_string()),
+            body,
+            ..Default::default()
+        }
+    }
+
+    pub fn method_return(call: &Message, body: Vec<Value>) -> Self {
+        Self {
+            typ: METHOD_RETURN,
+            reply_serial: Some(call.serial),
+            destination: call.sender.clone(),
+            body,
+            ..Default::default()
+        }
+    }
+
+    pub fn error(call: &Message, error_name: &str, message: &str) -> Self {
+        Self {
+            typ: ERROR,
+            reply_serial: Some(call.serial),
+            destination: call.sender.clone(),
+            error_name: Some(error_name.to_string()),
+            body: vec![Value::Str(message.to_string())],
+            ..Default::default()
+        }
+    }
+
+    pub fn signal(path: &str, interface: &str, member: &str, body: Vec<Value>) -> Self {
+        Self {
+            typ: SIGNAL,
+            path: Some(path.to_string()),
+            interface: Some(interface.to_string()),
+            member: Some(member.to_string()),
+            body,
+            ..Default::default()
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut body = Writer::default();
+        for value in &self.body {
+            body.write(value);
+        }
+        let signature: String = self.body.iter().map(Value::signature).collect();
+
+        let field = |code: u8, value: Value| {
+            Value::Struct(vec![Value::Byte(code), Value::Variant(Box::new(value))])
+        };
+        let mut fields = Vec::new();
+        let strings = [
+            (FIELD_INTERFACE, &self.interface),
+            (FIELD_MEMBER, &self.member),
+            (FIELD_ERROR_NAME, &self.error_name),
+            (FIELD_DESTINATION, &self.destination),
+            (FIELD_SENDER, &self.sender),
+        ];
+        if let Some(path) = &self.path {
+            fields.push(field(FIELD_PATH, Value::ObjectPath(path.clone())));
+        }
+        for (code, value) in strings.iter() {
+            if let Some(value) = value {
+                fields.push(field(*code, Value::Str(value.to_string())));
+            }
+        }
+        if let Some(reply_serial) = self.reply_serial {
+            fields.push(field(FIELD_REPLY_SERIAL, Value::UInt32(reply_serial)));
+        }
+        if !signature.is_empty() {
+            fields.push(field(FIELD_SIGNATURE, Value::Signature(signature)));
+        }
+
+        let mut message = Writer::default();
+        message
+            .buf
+            .extend_from_slice(&[b'l', self.typ, self.flags, 1]);
+        message.write_u32(body.buf.len() as u32);
+        message.write_u32(self.serial);
+        message.write(&Value::Array("(yv)".to_string(), fields));
+        message.pad(8);
+        message.buf.extend_from_slice(&body.buf);
+        message.buf
+    }
+
+    fn decode(header: &[u8], body: &[u8]) -> Result<Self> {
+        let mut reader = Reader {
+            buf: header,
+            pos: 0,
+        };
+        let fixed = reader.take(4)?;
+        if fixed[0] != b'l' {
+            bail!("big-endian D-Bus messages are not supported");
+        }
+        let mut message = Message {
+            typ: fixed[1],
+            flags: fixed[2],
+            ..Default::default()
+        };
+        reader.read_u32()?;
+        message.serial = reader.read_u32()?;
+
+        let mut signature = String::new();
+        if let Value::Array(_, fields) = reader.read("a(yv)")? {
+            for field in fields {
+                let (code, value) = match field {
+                    Value::Struct(mut f) if f.len() == 2 => match (f.remove(0), f.remove(0)) {
+                        (Value::Byte(code), Value::Variant(value)) => (code, *value),
+                        _ => continue,
+                    },
+                    _ => continue,
+                };
+                let string = value.as_str().map(str::to_string);
+                match code {
+                    FIELD_PATH => message.path = string,
+                    FIELD_INTERFACE => message.interface = string,
+                    FIELD_MEMBER => message.member = string,
+                    FIELD_ERROR_NAME => message.error_name = string,
+                    FIELD_REPLY_SERIAL => message.reply_serial = value.as_u32(),
+                    FIELD_DESTINATION => message.destination = string,
+                    FIELD_SENDER => message.sender = string,
+                    FIELD_SIGNATURE => signature = string.unwrap_or_default(),
+                    _ => {}
+                }
+            }
+        }
+
+        let mut reader = Reader { buf: body, pos: 0 };
+        for typ in split_signature(&signature)? {
+            message.body.push(reader.read(typ)?);
+        }
+        Ok(message)
+    }
+}
+
+/// A connection to a message bus.
+pub struct Connection {
+    stream: UnixStream,
+    serial: u32,
+    /// Messages that arrived while waiting for a reply.
+    pending: VecDeque<Message>,
+}
+
+impl Connection {
+    /// Connects to the system bus, at DBUS_SYSTEM_BUS_ADDRESS if it is set,
+    /// so that a private bus can stand in for it.
+    pub fn system() -> Result<Self> {
+        let address = env::var("DBUS_SYSTEM_BUS_ADDRESS")
+            .unwrap_or_else(|_| DEFAULT_SYSTEM_BUS_ADDRESS.to_string());
+        Self::open(&address)
+    }
+
+    /// Connects to the first `unix:path=` entry of the bus `address`.
+    pub fn open(address: &str) -> Result<Self> {
+        let path = address
+            .split(';')
+            .filter_map(|entry| entry.strip_prefix("unix:"))
+            .flat_map(|params| params.split(','))
+            .find_map(|param| param.strip_prefix("path="));
+        let path = match path {
+            Some(path) => path,
+            None => bail!("no supported transport in D-Bus address {:?}", address),
+        };
+        let stream = match UnixStream::connect(path) {
+            Ok(stream) => stream,
+            Err(e) => bail!("failed to connect to D-Bus at {}: {}", path, e),
+        };
+
+        let mut connection = Self {
+            stream,
+            serial: 0,
+            pending: VecDeque::new(),
+        };
+        connection.authenticate()?;
+        connection.call(Message::method_call(
+            BUS_DESTINATION,
+            BUS_PATH,
+            BUS_INTERFACE,
+            "Hello",
+            Vec::new(),
+        ))?;
+        Ok(connection)
+    }
+
+    fn authenticate(&mut self) -> Result<()> {
+        let uid = unistd::geteuid().as_raw().to_string();
+        let uid: String = uid.bytes().map(|b| format!("{:02x}", b)).collect();
+        self.stream
+            .write_all(format!("\0AUTH EXTERNAL {}\r\n", uid).as_bytes())?;
+
+        let mut line = Vec::new();
+        let mut byte = [0];
+        while !line.ends_with(b"\r\n") {
+            self.stream.read_exact(&mut byte)?;
+            line.push(byte[0]);
+        }
+        if !line.starts_with(b"OK ") {
+            bail!(
+                "D-Bus authentication failed: {}",
+                String::from_utf8_lossy(&line).trim()
+            );
+        }
+        self.stream.write_all(b"BEGIN\r\n")?;
+        Ok(())
+    }
+
+    /// Sends `message` and returns its serial.
+    pub fn send(&mut self, mut message: Message) -> Result<u32> {
+        self.serial += 1;
+        message.serial = self.serial;
+        self.stream.write_all(&message.encode())?;
+        Ok(message.serial)
+    }
+
+    /// Calls a method and returns the body of its reply.
+    pub fn call(&mut self, message: Message) -> Result<Vec<Value>> {
+        let serial = self.send(message)?;
+        loop {
+            let reply = self.read_from_stream(None)?;
+            if reply.reply_serial != Some(serial) {
+                self.pending.push_back(reply);
+                continue;
+            }
+            if reply.typ == ERROR {
+                let name = reply.error_name.unwrap_or_default();
+                match reply.body.first().and_then(Value::as_str) {
+                    Some(description) => bail!("{}: {}", name, description),
+                    None => bail!("{}", name),
+                }
+            }
+            return Ok(reply.body);
+        }
+    }
+
+    /// Returns the next message that isn't a reply to a call, waiting at
+    /// most until `deadline`.
+    pub fn read_message(&mut self, deadline: Option<Instant>) -> Result<Message> {
+        match self.pending.pop_front() {
+            Some(message) => Ok(message),
+            None => self.read_from_stream(deadline),
+        }
+    }
+
+    fn read_from_stream(&mut self, deadline: Option<Instant>) -> Result<Message> {
+        let timeout = match deadline {
+            Some(deadline) => {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                Some(timeout.max(Duration::from_millis(1)))
+            }
+            None => None,
+        };
+        self.stream.set_read_timeout(timeout)?;
+
+        let mut fixed = [0; 16];
+        self.stream.read_exact(&mut fixed)?;
+        let body_len = u32::from_le_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]) as usize;
+        let fields_len = u32::from_le_bytes([fixed[12], fixed[13], fixed[14], fixed[15]]) as usize;
+        let header_len = (16 + fields_len).div_ceil(8) * 8;
+
+        let mut header = vec![0; header_len];
+        header[..16].copy_from_slice(&fixed);
+        self.stream.read_exact(&mut header[16..])?;
+        let mut body = vec![0; body_len];
+        self.stream.read_exact(&mut body)?;
+        Message::decode(&header[..16 + fields_len], &body)
+    }
+
+    /// Asks the bus to route the messages matching `rule` to us.
+    pub fn add_match(&mut self, rule: &str) -> Result<()> {
+        self.call(Message::method_call(
+            BUS_DESTINATION,
+            BUS_PATH,
+            BUS_INTERFACE,
+            "AddMatch",
+            vec![Value::Str(rule.to_string())],
+        ))?;
+        Ok(())
+    }
+
+    /// Asks the bus for the well-known `name`.
+    pub fn request_name(&mut self, name: &str) -> Result<()> {
+        self.call(Message::method_call(
+            BUS_DESTINATION,
+            BUS_PATH,
+            BUS_INTERFACE,
+            "RequestName",
+            vec![Value::Str(name.to_string()), Value::UInt32(0)],
+        ))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Splits an encoded message into its header and body the way
+    /// `Connection` does.
+    fn split(bytes: &[u8]) -> (&[u8], &[u8]) {
+        let body_len = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+        let fields_len = u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]) as usize;
+        let header_len = (16 + fields_len).div_ceil(8) * 8;
+        assert_eq!(bytes.len(), header_len + body_len);
+        (&bytes[..16 + fields_len], &bytes[header_len..])
+    }
+
+    fn round_trip(message: &Message) -> Message {
+        let bytes = message.encode();
+        let (header, body) = split(&bytes);
+        Message::decode(header, body).unwrap()
+    }
+
+    fn encode(value: &Value) -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer.write(value);
+        writer.buf
+    }
+
+    #[test]
+    fn basic_values_round_trip() {
+        let body = vec![
+            Value::Byte(7),
+            Value::Bool(true),
+            Value::Int16(-2),
+            Value::UInt16(3),
+            Value::Int32(-4),
+            Value::UInt32(5),
+            Value::Int64(-6),
+            Value::UInt64(u64::MAX),
+            Value::Double(1.5),
+            Value::Str("youki".to_string()),
+            Value::ObjectPath("/org/freedesktop/systemd1/job/1".to_string()),
+            Value::Signature("a(sv)".to_string()),
+        ];
+        let message = Message::method_call("dest", "/path", "iface", "Member", body.clone());
+        assert_eq!(round_trip(&message).body, body);
+    }
+
+    #[test]
+    fn container_values_round_trip() {
+        let property = |name: &str, value: Value| {
+            Value::Struct(vec![
+                Value::Str(name.to_string()),
+                Value::Variant(Box::new(value)),
+            ])
+        };
+        let body = vec![
+            Value::Str("youki-c1.scope".to_string()),
+            // a byte before them makes the padding of the elements count
+            Value::Byte(1),
+            Value::Array(
+                "(sv)".to_string(),
+                vec![
+                    property(
+                        "PIDs",
+                        Value::Array("u".to_string(), vec![Value::UInt32(42)]),
+                    ),
+                    property("MemoryMax", Value::UInt64(1 << 30)),
+                    property("Delegate", Value::Bool(true)),
+                ],
+            ),
+            Value::Array("(sa(sv))".to_string(), Vec::new()),
+            Value::Array(
+                "{sv}".to_string(),
+                vec![Value::DictEntry(
+                    Box::new(Value::Str("key".to_string())),
+                    Box::new(Value::Variant(Box::new(Value::Int16(1)))),
+                )],
+            ),
+            Value::Array("t".to_string(), Vec::new()),
+        ];
+        let message = Message::method_call("dest", "/path", "iface", "Member", body.clone());
+        assert_eq!(round_trip(&message).body, body);
+    }
+
+    #[test]
+    fn header_fields_round_trip() {
+        let mut call = Message::method_call(
+            "org.example",
+            "/org/example",
+            "org.example.I",
+            "Do",
+            Vec::new(),
+        );
+        call.serial = 9;
+        call.sender = Some(":1.5".to_string());
+        let decoded = round_trip(&call);
+        assert_eq!(decoded.typ, METHOD_CALL);
+        assert_eq!(decoded.serial, 9);
+        assert_eq!(decoded.destination.as_deref(), Some("org.example"));
+        assert_eq!(decoded.path.as_deref(), Some("/org/example"));
+        assert_eq!(decoded.interface.as_deref(), Some("org.example.I"));
+        assert_eq!(decoded.member.as_deref(), Some("Do"));
+        assert_eq!(decoded.sender.as_deref(), Some(":1.5"));
+        assert!(decoded.body.is_empty());
+
+        let error = round_trip(&Message::error(&call, "org.example.Error", "it failed"));
+        assert_eq!(error.typ, ERROR);
+        assert_eq!(error.reply_serial, Some(9));
+        assert_eq!(error.destination.as_deref(), Some(":1.5"));
+        assert_eq!(error.error_name.as_deref(), Some("org.example.Error"));
+        assert_eq!(error.body, vec![Value::Str("it failed".to_string())]);
+
+        let reply = round_trip(&Message::method_return(&call, vec![Value::UInt32(1)]));
+        assert_eq!(reply.typ, METHOD_RETURN);
+        assert_eq!(reply.reply_serial, Some(9));
+
+        let signal = round_trip(&Message::signal(
+            "/org/example",
+            "org.example.I",
+            "Changed",
+            Vec::new(),
+        ));
+        assert_eq!(signal.typ, SIGNAL);
+        assert_eq!(signal.member.as_deref(), Some("Changed"));
+        assert_eq!(signal.destination, None);
+    }
+
+    #[test]
+    fn wire_format() {
+        assert_eq!(encode(&Value::Str("ab".to_string())), b"\x02\0\0\0ab\0");
+        assert_eq!(encode(&Value::Signature("as".to_string())), b"\x02as\0");
+        // the length of an array doesn't include the padding before its
+        // first element
+        assert_eq!(
+            encode(&Value::Array("t".to_string(), vec![Value::UInt64(1)])),
+            b"\x08\0\0\0\0\0\0\0\x01\0\0\0\0\0\0\0"
+        );
+        assert_eq!(
+            encode(&Value::Variant(Box::new(Value::UInt32(1)))),
+            b"\x01u\0\0\x01\0\0\0"
+        );
+    }
+
+    #[test]
+    fn signatures() {
+        let nested = Value::Array(
+            "{sv}".to_string(),
+            vec![Value::DictEntry(
+                Box::new(Value::Str(String::new())),
+                Box::new(Value::Variant(Box::new(Value::Byte(0)))),
+            )],
+        );
+        assert_eq!(nested.signature(), "a{sv}");
+        assert_eq!(
+            Value::Struct(vec![Value::Int32(0), nested]).signature(),
+            "(ia{sv})"
+        );
+
+        assert_eq!(
+            split_signature("a{sv}(ii)sa(sa(sv))").unwrap(),
+            vec!["a{sv}", "(ii)", "s", "a(sa(sv))"]
+        );
+        assert!(split_signature("").unwrap().is_empty());
+        for invalid in &["(ii", "a", "z", "a{sv"] {
+            assert!(split_signature(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn truncated_body() {
+        let message =
+            Message::method_call("d", "/p", "i", "m", vec![Value::Str("long".to_string())]);
+        let bytes = message.encode();
+        let (header, body) = split(&bytes);
+        assert!(Message::decode(header, &body[..body.len() - 2]).is_err());
+    }
+}
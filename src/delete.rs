@@ -1,10 +1,22 @@
-use std::{path::PathBuf, fs};
+use std::{path::{Path, PathBuf}, fs};
 
 use anyhow::{Result, bail};
 use clap::Parser;
-use nix::sys::signal;
+use nix::errno::Errno;
+use nix::mount::{self, MntFlags};
+use procfs::process::Process;
 
+use crate::cgroups;
+use crate::cgroups::systemd;
 use crate::container::{Container, ContainerStatus};
+use crate::hooks;
+use crate::kill;
+use crate::notify_socket::NOTIFY_FILE;
+use crate::signal;
+use crate::spec::Spec;
+
+/// The symlink to the console socket made by `tty::load_console_sockets`.
+const CONSOLE_SOCKET_LINK: &str = "console-stdout";
 
 /// Release any resources held by the container
 #[derive(Debug, Parser)]
@@ -20,12 +32,29 @@ impl Delete {
         let root_path = fs::canonicalize(root_path)?;
         let container_root = root_path.join(&self.container_id);
         if !container_root.exists() {
-            bail!("{} doesn't exists.", self.container_id)
+            if self.force {
+                return Ok(());
+            }
+            bail!("container {} does not exist", self.container_id)
         }
-        
-        let mut container = Container::load(container_root)?;
+
+        // A create that crashed halfway may have left no state, or only part
+        // of it, which must not keep what remains from being cleaned up.
+        let mut container = match Container::load(container_root.clone()) {
+            Ok(container) => container,
+            Err(e) => {
+                log::warn!("the state of {} could not be loaded: {}", self.container_id, e);
+                Container::new(
+                    &self.container_id,
+                    ContainerStatus::Stopped,
+                    None,
+                    "",
+                    &container_root,
+                )?
+            }
+        };
         container.refresh_status()?;
-        
+
         // Check if container is allowed to be deleted based on container status.
         match container.status() {
             ContainerStatus::Stopped => {}
@@ -34,23 +63,89 @@ impl Delete {
                 // force flag is set. In the force case, we need to clean up any
                 // processes associated with containers.
                 if self.force {
-                    container.do_kill(signal::Signal::SIGKILL)?;
-                    container.set_status(ContainerStatus::Stopped).save()?;
-                } 
+                    if let Err(e) = kill::kill_all(&container, libc::SIGKILL) {
+                        log::warn!("{}; killing only its init", e);
+                        container.do_kill(nix::sys::signal::Signal::SIGKILL)?;
+                    }
+                    container.set_status(ContainerStatus::Stopped);
+                }
             }
         }
-        if container.can_delete() {
-            if container.root.exists() {
-                fs::remove_dir_all(&container.root)?;
-            }
-            log::debug!("{} was deleted successfully", container.id());
-            std::process::exit(0)
-        } else {
+        if !container.can_delete() {
             bail!(
                 "{} could not be deleted because it was {:?}",
                 container.id(),
                 container.status()
             )
         }
+
+        let cgroup_path = match container.cgroup_path() {
+            Some(cgroup_path) => cgroup_path.to_path_buf(),
+            None => cgroups::path_of("", container.id()),
+        };
+        if self.force {
+            kill_stragglers(&cgroup_path)?;
+        }
+        if let Some(unit) = container.systemd_unit() {
+            systemd::stop_unit(unit)?;
+        }
+        cgroups::remove(&cgroup_path)?;
+
+        unmount_all(&container.root)?;
+        for file in &[NOTIFY_FILE, CONSOLE_SOCKET_LINK] {
+            match fs::remove_file(container.root.join(file)) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+
+        // Failing poststop hooks don't stop the deletion.
+        if !container.state.bundle.is_empty() {
+            let config = Path::new(&container.state.bundle).join("config.json");
+            if let Some(hooks) = Spec::load(config).ok().and_then(|spec| spec.hooks) {
+                if let Err(e) = hooks::run_hooks(&hooks.poststop, &container.state) {
+                    log::warn!("poststop hook of {} failed: {}", container.id(), e);
+                }
+            }
+        }
+
+        fs::remove_dir_all(&container.root)?;
+        log::debug!("{} was deleted successfully", container.id());
+        Ok(())
+    }
+}
+
+/// Kills the processes left in the cgroup `path`, such as the descendants
+/// of an init that has already exited.
+fn kill_stragglers(path: &Path) -> Result<()> {
+    if !cgroups::exists(path)? || cgroups::kill(path)? {
+        return Ok(());
+    }
+    for pid in cgroups::pids(path)? {
+        match signal::kill(pid, libc::SIGKILL) {
+            Err(e) if e.downcast_ref() == Some(&nix::Error::Sys(Errno::ESRCH)) => {}
+            result => result?,
+        }
+    }
+    Ok(())
+}
+
+/// Unmounts whatever is still mounted in the container directory, such as
+/// bind mounts pinning namespaces, deepest first.
+pub fn unmount_all(container_root: &Path) -> Result<()> {
+    let mut mount_points: Vec<PathBuf> = Process::myself()?
+        .mountinfo()?
+        .into_iter()
+        .map(|m| m.mount_point)
+        .filter(|mount_point| mount_point.starts_with(container_root))
+        .collect();
+    mount_points.sort();
+    for mount_point in mount_points.iter().rev() {
+        log::debug!("unmount {:?}", mount_point);
+        match mount::umount2(mount_point, MntFlags::MNT_DETACH) {
+            Err(nix::Error::Sys(Errno::EINVAL)) | Err(nix::Error::Sys(Errno::ENOENT)) => {}
+            result => result?,
+        }
     }
+    Ok(())
 }
@@ -0,0 +1,189 @@
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::cgroups::stats;
+use crate::container::{Container, ContainerStatus};
+
+/// How often the container is checked for lifecycle and OOM events.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// How long to wait for the exit code to be recorded once the container's
+/// process has gone.
+const EXIT_CODE_GRACE: Duration = Duration::from_secs(1);
+
+/// Stream the events of a container as newline-delimited JSON
+#[derive(Debug, Parser)]
+pub struct Events {
+    pub container_id: String,
+    /// print a single resource usage sample and exit
+    #[clap(long)]
+    pub stats: bool,
+    /// time between resource usage samples, e.g. 5s or 500ms, in seconds
+    /// without a unit
+    #[clap(long, default_value = "5s", value_parser = parse_interval)]
+    pub interval: Duration,
+}
+
+/// The envelope of every event, as emitted by `runc events`.
+#[derive(Serialize, Debug)]
+struct Event<'a, T: Serialize> {
+    #[serde(rename = "type")]
+    typ: &'a str,
+    id: &'a str,
+    data: T,
+}
+
+impl Events {
+    pub fn exec(&self, root_path: PathBuf) -> Result<()> {
+        let container_root = root_path.join(&self.container_id);
+        if !container_root.exists() {
+            bail!("container {} does not exist", self.container_id)
+        }
+        if self.stats {
+            let container = Container::load(container_root)?;
+            return match container.cgroup_path() {
+                Some(cgroup_path) => self.emit("stats", stats::collect(cgroup_path)?),
+                None => bail!("{} has no cgroup to report on", container.id()),
+            };
+        }
+        let interval = self.interval;
+
+        let mut last_status: Option<ContainerStatus> = None;
+        let mut oom_kills: Option<u64> = None;
+        let mut next_stats = Instant::now();
+        let mut stopped_at: Option<Instant> = None;
+        loop {
+            let mut container = Container::load(container_root.clone())?;
+            container.refresh_status()?;
+
+            let status = container.status();
+            if last_status != Some(status) {
+                self.emit("state", json!({ "status": status }))?;
+                last_status = Some(status);
+            }
+
+            if let Some(cgroup_path) = container.cgroup_path() {
+                if let Ok(count) = stats::oom_kill_count(cgroup_path) {
+                    if matches!(oom_kills, Some(last) if count > last) {
+                        self.emit("oom", json!({}))?;
+                    }
+                    oom_kills = Some(count);
+                }
+
+                if Instant::now() >= next_stats {
+                    self.emit("stats", stats::collect(cgroup_path)?)?;
+                    next_stats += interval;
+                }
+            }
+
+            if let ContainerStatus::Stopped = status {
+                let stopped_at = *stopped_at.get_or_insert_with(Instant::now);
+                if container.exit_code().is_some() || stopped_at.elapsed() >= EXIT_CODE_GRACE {
+                    self.emit("exit", json!({ "exitCode": container.exit_code() }))?;
+                    return Ok(());
+                }
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    fn emit<T: Serialize>(&self, typ: &str, data: T) -> Result<()> {
+        let event = Event {
+            typ,
+            id: &self.container_id,
+            data,
+        };
+        println!("{}", serde_json::to_string(&event)?);
+        Ok(())
+    }
+}
+
+/// Parses an interval in the duration format of Go that runc takes, such as
+/// 5s, 1m30s or 500ms, or a bare number of seconds.
+fn parse_interval(value: &str) -> Result<Duration> {
+    let interval = match value.parse::<u64>() {
+        Ok(secs) => Duration::from_secs(secs),
+        Err(_) => parse_go_duration(value)?,
+    };
+    if interval.is_zero() {
+        bail!("the interval must be greater than 0");
+    }
+    Ok(interval)
+}
+
+fn parse_go_duration(value: &str) -> Result<Duration> {
+    if value.is_empty() {
+        bail!("the interval must not be empty");
+    }
+    let mut total = Duration::ZERO;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let number: f64 = match rest[..digits].parse() {
+            Ok(number) => number,
+            Err(_) => bail!("{:?} is not a valid duration", value),
+        };
+        rest = &rest[digits..];
+        let unit = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        // in nanoseconds, which keeps fractions such as 1.5h exact
+        let scale = match &rest[..unit] {
+            "ns" => 1.0,
+            "us" | "\u{b5}s" | "\u{3bc}s" => 1e3,
+            "ms" => 1e6,
+            "s" => 1e9,
+            "m" => 60e9,
+            "h" => 3600e9,
+            _ => bail!("{:?} is not a valid duration", value),
+        };
+        rest = &rest[unit..];
+        total += Duration::from_nanos((number * scale).round() as u64);
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn go_durations() {
+        let cases = [
+            ("5s", Duration::from_secs(5)),
+            ("1m30s", Duration::from_secs(90)),
+            ("500ms", Duration::from_millis(500)),
+            ("1.5h", Duration::from_secs(5400)),
+            ("1h2m3s4ms5us6ns", Duration::new(3723, 4_005_006)),
+            ("250\u{b5}s", Duration::from_micros(250)),
+            ("250\u{3bc}s", Duration::from_micros(250)),
+            (".5s", Duration::from_millis(500)),
+        ];
+        for (value, expected) in cases.iter() {
+            assert_eq!(parse_go_duration(value).unwrap(), *expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn invalid_go_durations() {
+        for value in &["", "5", "5x", "5 s", "s", "1m5", "-1s", "1..5s", "5sec"] {
+            assert!(parse_go_duration(value).is_err(), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn intervals() {
+        assert_eq!(parse_interval("10").unwrap(), Duration::from_secs(10));
+        assert_eq!(parse_interval("2m").unwrap(), Duration::from_secs(120));
+        assert!(parse_interval("0").is_err());
+        assert!(parse_interval("0s").is_err());
+    }
+}
@@ -0,0 +1,203 @@
+use std::fs;
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use nix::fcntl::{self, OFlag};
+use nix::sched::{self, CloneFlags};
+use nix::sys::stat::Mode;
+use nix::sys::wait::{waitpid, WaitStatus};
+use nix::unistd::{self, Pid};
+
+use crate::cgroups;
+use crate::container::Container;
+use crate::process::{fork, Process};
+use crate::spec;
+use crate::stdio;
+use crate::tty;
+use crate::utils;
+
+/// Namespaces in the order they are joined. The user namespace has to come
+/// first so that the others can be joined with the privileges it grants.
+const NAMESPACES: &[(&str, CloneFlags)] = &[
+    ("user", CloneFlags::CLONE_NEWUSER),
+    ("ipc", CloneFlags::CLONE_NEWIPC),
+    ("uts", CloneFlags::CLONE_NEWUTS),
+    ("net", CloneFlags::CLONE_NEWNET),
+    ("pid", CloneFlags::CLONE_NEWPID),
+    ("cgroup", CloneFlags::CLONE_NEWCGROUP),
+    ("mnt", CloneFlags::CLONE_NEWNS),
+];
+
+/// Execute a new process inside a running container
+#[derive(Debug, Parser)]
+pub struct Exec {
+    pub container_id: String,
+    /// path to a process.json describing the process to run
+    #[clap(short, long)]
+    pub process: Option<PathBuf>,
+    /// allocate a pseudo-TTY, which is sent to --console-socket
+    #[clap(short, long, requires = "console_socket")]
+    pub tty: bool,
+    #[clap(long)]
+    pub console_socket: Option<PathBuf>,
+    /// detach from the process and return once it has started
+    #[clap(short, long)]
+    pub detach: bool,
+    #[clap(long)]
+    pub pid_file: Option<PathBuf>,
+    /// current working directory inside the container
+    #[clap(long)]
+    pub cwd: Option<String>,
+    /// additional environment variables in the KEY=VALUE form
+    #[clap(short, long)]
+    pub env: Vec<String>,
+    /// user to run the process as, in the uid[:gid] form
+    #[clap(short, long)]
+    pub user: Option<String>,
+    /// command to execute, given after `--`
+    #[clap(last = true)]
+    pub command: Vec<String>,
+}
+
+impl Exec {
+    pub fn exec(&self, root_path: PathBuf) -> Result<()> {
+        let container_root = root_path.join(&self.container_id);
+        if !container_root.exists() {
+            bail!("container {} does not exist", self.container_id)
+        }
+        let mut container = Container::load(container_root)?;
+        container.refresh_status()?;
+        if !container.can_exec() {
+            bail!(
+                "{} could not be executed because it was {:?}",
+                container.id(),
+                container.status()
+            )
+        }
+        let init_pid = container.pid().unwrap();
+
+        let process = self.load_process(&container)?;
+        if process.args.is_empty() {
+            bail!("no command was given to execute");
+        }
+        let csocketfd = match &self.console_socket {
+            Some(console_socket) => Some(tty::connect_console_socket(console_socket)?),
+            None if process.terminal => bail!("--console-socket is required to use a terminal"),
+            None => None,
+        };
+        // the namespaces are opened from the host's view of /proc, before
+        // any of them is joined.
+        let namespaces = open_namespaces(init_pid)?;
+
+        match fork::fork_exec()? {
+            Process::Parent(mut parent) => {
+                let pid = parent.wait_for_child_ready()?;
+                log::debug!(
+                    "{} executes {:?} as {}",
+                    self.container_id,
+                    process.args,
+                    pid
+                );
+                if let Some(pid_file) = &self.pid_file {
+                    utils::write_pid_file(pid_file, pid)?;
+                }
+                if self.detach {
+                    return Ok(());
+                }
+
+                let child_pid = parent
+                    .child_pid()
+                    .expect("the intermediate process should have been forked.");
+                let exit_code = match waitpid(child_pid, None)? {
+                    WaitStatus::Exited(_, status) => status,
+                    WaitStatus::Signaled(_, sig, _) => 128 + sig as i32,
+                    status => bail!("unexpected wait status {:?}", status),
+                };
+                std::process::exit(exit_code)
+            }
+            Process::Child(child) => {
+                // a rootless container may run without a cgroup of its own,
+                // leaving the init in one the user can't write to.
+                if container.cgroup_path().is_some() {
+                    cgroups::join(init_pid, unistd::getpid())?;
+                }
+                for (space, fd) in namespaces {
+                    sched::setns(fd, space)?;
+                    unistd::close(fd)?;
+                }
+
+                // We fork again, for the same reason as `create`, to actually
+                // enter the PID namespace.
+                match fork::fork_init(child, None)? {
+                    Process::Init(mut init) => {
+                        init.ready()?;
+                        if let Some(csocketfd) = csocketfd {
+                            tty::ready(csocketfd)?;
+                        }
+                        // no activation sockets are passed on to exec'd
+                        // processes
+                        stdio::clear_listen_env();
+                        utils::set_process(&process)?;
+                        utils::do_exec(&process.args[0], &process.args)?;
+                        Ok(())
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn load_process(&self, container: &Container) -> Result<spec::Process> {
+        if let Some(process_path) = &self.process {
+            let file = fs::File::open(process_path)?;
+            let mut process: spec::Process = serde_json::from_reader(&file)?;
+            process.terminal |= self.tty;
+            return Ok(process);
+        }
+
+        // Everything but the command defaults to the container's own process.
+        let config = Path::new(&container.state.bundle).join("config.json");
+        let mut process = spec::Spec::load(config)?.process;
+        process.args = self.command.clone();
+        process.terminal = self.tty;
+        for env in &self.env {
+            if !env.contains('=') {
+                bail!("--env {:?} is not in the KEY=VALUE form", env);
+            }
+        }
+        process.env.extend(self.env.iter().cloned());
+        if let Some(cwd) = &self.cwd {
+            process.cwd = cwd.clone();
+        }
+        if let Some(user) = &self.user {
+            let (uid, gid) = match user.split_once(':') {
+                Some((uid, gid)) => (uid.parse()?, gid.parse()?),
+                None => (user.parse()?, process.user.gid),
+            };
+            process.user.uid = uid;
+            process.user.gid = gid;
+        }
+        Ok(process)
+    }
+}
+
+fn open_namespaces(pid: Pid) -> Result<Vec<(CloneFlags, RawFd)>> {
+    let mut namespaces = Vec::new();
+    for &(name, space) in NAMESPACES {
+        let path = PathBuf::from(format!("/proc/{}/ns/{}", pid, name));
+        if !path.exists() {
+            log::debug!("{} namespace is not supported", name);
+            continue;
+        }
+        // setns(2) refuses to join the user namespace we already belong to.
+        if fs::read_link(&path)? == fs::read_link(format!("/proc/self/ns/{}", name))? {
+            continue;
+        }
+        let fd = fcntl::open(&path, OFlag::O_RDONLY | OFlag::O_CLOEXEC, Mode::empty())?;
+        namespaces.push((space, fd));
+    }
+    Ok(namespaces)
+}
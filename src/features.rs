@@ -0,0 +1,175 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use clap::Parser;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::cgroups;
+use crate::rootfs;
+use crate::spec::{
+    self, Arch, LinuxCapabilityType, LinuxNamespaceType, LinuxSeccompAction, LinuxSeccompOperator,
+};
+
+/// The oldest version of the runtime spec whose configs are accepted.
+const OCI_VERSION_MIN: &str = "1.0.0";
+
+const NAMESPACES: &[LinuxNamespaceType] = &[
+    LinuxNamespaceType::Mount,
+    LinuxNamespaceType::Cgroup,
+    LinuxNamespaceType::Uts,
+    LinuxNamespaceType::Ipc,
+    LinuxNamespaceType::User,
+    LinuxNamespaceType::Pid,
+    LinuxNamespaceType::Network,
+];
+
+const SECCOMP_ACTIONS: &[LinuxSeccompAction] = &[
+    LinuxSeccompAction::ScmpActKill,
+    LinuxSeccompAction::ScmpActTrap,
+    LinuxSeccompAction::ScmpActErrno,
+    LinuxSeccompAction::ScmpActTrace,
+    LinuxSeccompAction::ScmpActAllow,
+];
+
+const SECCOMP_OPERATORS: &[LinuxSeccompOperator] = &[
+    LinuxSeccompOperator::ScmpCmpNe,
+    LinuxSeccompOperator::ScmpCmpLt,
+    LinuxSeccompOperator::ScmpCmpLe,
+    LinuxSeccompOperator::ScmpCmpEq,
+    LinuxSeccompOperator::ScmpCmpGe,
+    LinuxSeccompOperator::ScmpCmpGt,
+    LinuxSeccompOperator::ScmpCmpMaskedEq,
+];
+
+const SECCOMP_ARCHS: &[Arch] = &[
+    Arch::ScmpArchNative,
+    Arch::ScmpArchX86,
+    Arch::ScmpArchX86_64,
+    Arch::ScmpArchX32,
+    Arch::ScmpArchArm,
+    Arch::ScmpArchAarch64,
+    Arch::ScmpArchMips,
+    Arch::ScmpArchMips64,
+    Arch::ScmpArchMips64n32,
+    Arch::ScmpArchMipsel,
+    Arch::ScmpArchMipsel64,
+    Arch::ScmpArchMipsel64n32,
+    Arch::ScmpArchPpc,
+    Arch::ScmpArchPpc64,
+    Arch::ScmpArchPpc64le,
+    Arch::ScmpArchS390,
+    Arch::ScmpArchS390x,
+];
+
+/// Show the features supported by the runtime as a features.json document
+#[derive(Debug, Parser)]
+pub struct Features {}
+
+/// The features.json document of the runtime spec.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct FeaturesInfo {
+    oci_version_min: String,
+    oci_version_max: String,
+    hooks: Vec<String>,
+    mount_options: Vec<String>,
+    linux: LinuxFeatures,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LinuxFeatures {
+    namespaces: Vec<LinuxNamespaceType>,
+    capabilities: Vec<String>,
+    cgroup: CgroupFeatures,
+    seccomp: SeccompFeatures,
+    apparmor: Enabled,
+    selinux: Enabled,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CgroupFeatures {
+    v1: bool,
+    v2: bool,
+    systemd: bool,
+    systemd_user: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SeccompFeatures {
+    enabled: bool,
+    actions: Vec<LinuxSeccompAction>,
+    operators: Vec<LinuxSeccompOperator>,
+    archs: Vec<Arch>,
+}
+
+#[derive(Serialize, Debug)]
+struct Enabled {
+    enabled: bool,
+}
+
+impl Features {
+    pub fn exec(&self) -> Result<()> {
+        let v2 = cgroups::is_v2();
+        let features = FeaturesInfo {
+            oci_version_min: OCI_VERSION_MIN.to_string(),
+            oci_version_max: spec::OCI_VERSION.to_string(),
+            // only poststop hooks are run yet
+            hooks: vec!["poststop".to_string()],
+            mount_options: rootfs::supported_mount_options()
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            linux: LinuxFeatures {
+                namespaces: NAMESPACES.to_vec(),
+                capabilities: capabilities(),
+                cgroup: CgroupFeatures {
+                    v1: !v2 && Path::new(cgroups::DEFAULT_CGROUP_ROOT).exists(),
+                    v2,
+                    systemd: cgroups::systemd::is_booted(),
+                    systemd_user: false,
+                },
+                // the types are understood, but the filter is not loaded yet
+                seccomp: SeccompFeatures {
+                    enabled: false,
+                    actions: SECCOMP_ACTIONS.to_vec(),
+                    operators: SECCOMP_OPERATORS.to_vec(),
+                    archs: SECCOMP_ARCHS.to_vec(),
+                },
+                apparmor: Enabled {
+                    enabled: apparmor_enabled(),
+                },
+                selinux: Enabled {
+                    enabled: selinux_enabled(),
+                },
+            },
+        };
+        println!("{}", serde_json::to_string_pretty(&features)?);
+        Ok(())
+    }
+}
+
+/// Returns the capabilities that a config.json may name, sorted by name.
+fn capabilities() -> Vec<String> {
+    let mut capabilities: Vec<String> = caps::all()
+        .into_iter()
+        .map(|cap| cap.to_string())
+        .filter(|name| serde_json::from_value::<LinuxCapabilityType>(json!(name)).is_ok())
+        .collect();
+    capabilities.sort();
+    capabilities
+}
+
+fn apparmor_enabled() -> bool {
+    fs::read_to_string("/sys/module/apparmor/parameters/enabled")
+        .map(|enabled| enabled.trim() == "Y")
+        .unwrap_or(false)
+}
+
+fn selinux_enabled() -> bool {
+    Path::new("/sys/fs/selinux/enforce").exists()
+}
@@ -0,0 +1,143 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use clap::Parser;
+
+use crate::container::Container;
+use crate::delete::Delete;
+
+/// How long a container directory may lack a state before it is considered
+/// left behind, since create makes the directory before it saves the state.
+const STATELESS_GRACE_PERIOD: Duration = Duration::from_secs(60);
+
+/// Remove the containers left behind by a crashed create or a reboot
+#[derive(Debug, Parser)]
+pub struct Gc {
+    /// only print the IDs of the containers that would be removed
+    #[clap(short = 'n', long)]
+    pub dry_run: bool,
+}
+
+impl Gc {
+    pub fn exec(&self, root_path: PathBuf) -> Result<()> {
+        let mut failed = 0;
+        for id in stale_containers(&root_path)? {
+            if self.dry_run {
+                println!("{}", id);
+                continue;
+            }
+            match remove(&root_path, &id) {
+                Ok(()) => println!("{}", id),
+                Err(e) => {
+                    eprintln!("failed to remove {}: {}", id, e);
+                    failed += 1;
+                }
+            }
+        }
+        if failed > 0 {
+            bail!("{} stale container(s) could not be removed", failed);
+        }
+        Ok(())
+    }
+}
+
+/// Removes the stale containers under `root_path`, as the global --gc flag
+/// does before running a command. Containers that can't be removed are
+/// only warned about, so that they don't get in the way of the command.
+pub fn collect(root_path: &Path) -> Result<()> {
+    for id in stale_containers(root_path)? {
+        match remove(root_path, &id) {
+            Ok(()) => log::info!("removed stale container {}", id),
+            Err(e) => log::warn!("failed to remove stale container {}: {}", id, e),
+        }
+    }
+    Ok(())
+}
+
+/// Returns the IDs of the containers under `root_path` whose runtime is
+/// gone, see `Container::is_stale`, including the directories a create
+/// crashed in before saving any state.
+pub fn stale_containers(root_path: &Path) -> Result<Vec<String>> {
+    let mut ids = Vec::new();
+    for entry in fs::read_dir(root_path)? {
+        let entry = entry?;
+        let container_root = entry.path();
+        if !container_root.is_dir() {
+            continue;
+        }
+        let stale = match Container::load(container_root) {
+            Ok(container) => container.is_stale(),
+            Err(_) => entry
+                .metadata()?
+                .modified()?
+                .elapsed()
+                .is_ok_and(|age| age > STATELESS_GRACE_PERIOD),
+        };
+        if stale {
+            ids.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    ids.sort();
+    Ok(ids)
+}
+
+/// Releases what `id` holds, its cgroup, mounts, sockets and state, the way
+/// a forced delete does. Its init is gone, so only the processes left in
+/// its cgroup are killed.
+fn remove(root_path: &Path, id: &str) -> Result<()> {
+    Delete {
+        container_id: id.to_string(),
+        force: true,
+    }
+    .exec(root_path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use nix::unistd::{self, Pid};
+
+    use super::*;
+    use crate::container::ContainerStatus;
+
+    /// Saves a container being created by `creator` under `root_path`.
+    fn creating(root_path: &Path, id: &str, creator: Option<Pid>) {
+        let container_root = root_path.join(id);
+        fs::create_dir_all(&container_root).unwrap();
+        let mut container = Container::new(
+            id,
+            ContainerStatus::Creating,
+            None,
+            "/bundle",
+            &container_root,
+        )
+        .unwrap();
+        if let Some(creator) = creator {
+            container.set_creator(creator);
+        }
+        container.save().unwrap();
+    }
+
+    #[test]
+    fn creating_containers() {
+        let root_path = std::env::temp_dir().join(format!("gc-{}", std::process::id()));
+        let mut exited = Command::new("true").spawn().unwrap();
+        let exited_pid = Pid::from_raw(exited.id() as i32);
+        exited.wait().unwrap();
+
+        // as create and restore leave them while they are at work
+        creating(&root_path, "live", Some(unistd::getpid()));
+        creating(&root_path, "crashed", Some(exited_pid));
+        creating(&root_path, "unknown", None);
+        let stale = stale_containers(&root_path);
+        fs::remove_dir_all(&root_path).unwrap();
+
+        assert_eq!(
+            stale.unwrap(),
+            vec!["crashed".to_string(), "unknown".to_string()]
+        );
+    }
+}
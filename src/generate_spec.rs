@@ -0,0 +1,38 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use nix::unistd;
+
+use crate::spec::Spec;
+
+/// Create a new config.json with default values in the bundle
+#[derive(Debug, Parser)]
+pub struct GenerateSpec {
+    /// path to the bundle the config.json is written to
+    #[clap(short, long, default_value = ".")]
+    pub bundle: PathBuf,
+    /// generate a configuration for a container run by an unprivileged user
+    #[clap(long)]
+    pub rootless: bool,
+}
+
+impl GenerateSpec {
+    pub fn exec(&self) -> Result<()> {
+        let config = self.bundle.join("config.json");
+        if config.exists() {
+            bail!("{} already exists", config.display());
+        }
+
+        let mut spec = Spec::default();
+        if self.rootless {
+            spec.set_rootless(unistd::geteuid().as_raw(), unistd::getegid().as_raw());
+        }
+
+        let file = fs::File::create(&config)?;
+        serde_json::to_writer_pretty(&file, &spec)?;
+        log::debug!("{} was generated.", config.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+use std::io::{ErrorKind, Write};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+
+use crate::container::State;
+use crate::spec::Hook;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Runs `hooks` in order, stopping at the first one that fails.
+pub fn run_hooks(hooks: &[Hook], state: &State) -> Result<()> {
+    for hook in hooks {
+        run_hook(hook, state)?;
+    }
+    Ok(())
+}
+
+/// Runs a hook with the state of the container on its stdin, killing it if
+/// it doesn't finish within its timeout.
+fn run_hook(hook: &Hook, state: &State) -> Result<()> {
+    log::debug!("run hook {:?}", hook.path);
+    let mut command = Command::new(&hook.path);
+    if let Some((arg0, args)) = hook.args.split_first() {
+        command.arg0(arg0).args(args);
+    }
+    command
+        .env_clear()
+        .envs(hook.env.iter().filter_map(|env| env.split_once('=')))
+        .stdin(Stdio::piped());
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => bail!("failed to run hook {:?}: {}", hook.path, e),
+    };
+
+    let state = serde_json::to_vec(state)?;
+    if let Err(e) = child.stdin.take().unwrap().write_all(&state) {
+        // the hook doesn't have to read its stdin
+        if e.kind() != ErrorKind::BrokenPipe {
+            return Err(e.into());
+        }
+    }
+
+    let deadline = hook
+        .timeout
+        .map(|t| Instant::now() + Duration::from_secs(t));
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if matches!(deadline, Some(deadline) if Instant::now() >= deadline) {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("hook {:?} timed out", hook.path);
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    if !status.success() {
+        bail!("hook {:?} failed with {}", hook.path, status);
+    }
+    Ok(())
+}
@@ -2,15 +2,21 @@ use std::{path::PathBuf, fs};
 
 use anyhow::{Result, bail};
 use clap::Parser;
-use nix::sys::signal as nix_signal;
+use nix::errno::Errno;
 
-use crate::container::{Container, ContainerStatus};
+use crate::cgroups;
+use crate::container::Container;
+use crate::ps;
 use crate::signal;
 
 #[derive(Debug, Parser)]
 pub struct Kill {
     pub container_id: String,
+    #[clap(default_value = "SIGTERM")]
     pub signal: String,
+    /// send the signal to every process of the container, not only its init
+    #[clap(short, long)]
+    pub all: bool,
 }
 
 impl Kill {
@@ -18,25 +24,48 @@ impl Kill {
         let root_path = fs::canonicalize(root_path)?;
         let container_root = root_path.join(&self.container_id);
         if !container_root.exists() {
-            bail!("{} doesn't exists.", self.container_id)
+            bail!("container {} does not exist", self.container_id)
         }
 
         let mut container = Container::load(container_root)?;
         container.refresh_status()?;
         if container.can_kill() {
             let sig = signal::from_str(&self.signal.as_str())?;
-            nix_signal::kill(container.pid().unwrap(), sig)?;
-            container.set_status(ContainerStatus::Stopped).save()?;
+            if self.all {
+                kill_all(&container, sig)?;
+            } else {
+                signal::kill(container.pid().unwrap(), sig)?;
+            }
+            // The status is not changed here: the container is only stopped
+            // once its init has exited, which the signal may not cause.
 
             log::debug!("{} was killed.", self.container_id);
 
             std::process::exit(0)
         } else {
-            bail!(
-                "{} could not be deleted because it was {:?}",
-                container.id(),
-                container.status()
-            )
+            // containerd recognizes a container that has already exited by
+            // runc's wording
+            bail!("{} could not be killed: container not running", container.id())
         }
     }
 }
+
+/// Sends `sig` to every process of the container, through the cgroup when
+/// it exists.
+pub fn kill_all(container: &Container, sig: libc::c_int) -> Result<()> {
+    if sig == libc::SIGKILL {
+        if let Some(cgroup_path) = container.cgroup_path() {
+            if cgroups::kill(cgroup_path)? {
+                return Ok(());
+            }
+        }
+    }
+    for pid in ps::container_pids(container)? {
+        match signal::kill(pid, sig) {
+            // the process exited in the meantime
+            Err(e) if e.downcast_ref() == Some(&nix::Error::Sys(Errno::ESRCH)) => {}
+            result => result?,
+        }
+    }
+    Ok(())
+}
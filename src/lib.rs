@@ -1,18 +1,38 @@
 pub mod capabilities;
+pub mod cgroups;
+pub mod checkpoint;
 pub mod container;
 pub mod cond;
 pub mod create;
+pub mod criu;
+pub mod dbus;
 pub mod delete;
+pub mod events;
+pub mod exec;
+pub mod features;
+pub mod gc;
+pub mod generate_spec;
+pub mod hooks;
 pub mod kill;
+pub mod list;
 pub mod logger;
 pub mod notify_socket;
+pub mod pause;
 pub mod process;
+pub mod ps;
+pub mod restore;
+pub mod resume;
 pub mod rootfs;
+pub mod rootless;
+pub mod run;
 pub mod signal;
 pub mod spec;
 pub mod start;
 pub mod state;
+pub mod stats;
 pub mod stdio;
 pub mod stop;
 pub mod tty;
+pub mod update;
 pub mod utils;
+pub mod validate;
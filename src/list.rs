@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use clap::Parser;
+use nix::unistd::{Uid, User};
+use serde::Serialize;
+
+use crate::container::{Container, ContainerStatus};
+use crate::utils;
+
+/// List the containers created under the root directory
+#[derive(Debug, Parser)]
+pub struct List {
+    /// output format
+    #[clap(short, long, default_value = "table", value_parser = ["table", "json"])]
+    pub format: String,
+    /// display only the container IDs
+    #[clap(short, long)]
+    pub quiet: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ContainerInfo {
+    id: String,
+    pid: Option<i32>,
+    status: ContainerStatus,
+    bundle: String,
+    rootfs: String,
+    created: String,
+    owner: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    started: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    finished: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_signal: Option<i32>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    annotations: HashMap<String, String>,
+}
+
+impl List {
+    pub fn exec(&self, root_path: PathBuf) -> Result<()> {
+        let mut containers = Vec::new();
+        for entry in fs::read_dir(&root_path)? {
+            let container_root = entry?.path();
+            if !container_root.is_dir() {
+                continue;
+            }
+
+            // A broken container shouldn't hide the others from the list.
+            match load_info(&container_root) {
+                Ok(info) => containers.push(info),
+                Err(e) => eprintln!("failed to load {:?}: {}", container_root, e),
+            }
+        }
+        containers.sort_by(|a, b| a.id.cmp(&b.id));
+
+        if self.quiet {
+            for container in &containers {
+                println!("{}", container.id);
+            }
+        } else if self.format == "json" {
+            println!("{}", serde_json::to_string_pretty(&containers)?);
+        } else {
+            let rows: Vec<Vec<String>> = containers
+                .into_iter()
+                .map(|c| {
+                    vec![
+                        c.id,
+                        c.pid.unwrap_or(0).to_string(),
+                        c.status.to_string(),
+                        c.bundle,
+                        c.created,
+                        c.owner,
+                        c.exit_code.map(|code| code.to_string()).unwrap_or_default(),
+                    ]
+                })
+                .collect();
+            utils::print_table(
+                &["ID", "PID", "STATUS", "BUNDLE", "CREATED", "OWNER", "EXIT"],
+                &rows,
+            );
+        }
+        Ok(())
+    }
+}
+
+fn load_info(container_root: &Path) -> Result<ContainerInfo> {
+    let mut container = Container::load(container_root.to_path_buf())?;
+    container.refresh_status()?;
+
+    let metadata = fs::metadata(container_root)?;
+    // containers created before createdAt was recorded fall back to their
+    // directory
+    let created: DateTime<Local> = match container.created_at() {
+        Some(created_at) => created_at.into(),
+        None => metadata.created().or_else(|_| metadata.modified())?.into(),
+    };
+    let uid = container.owner().unwrap_or_else(|| metadata.uid());
+    let owner = match User::from_uid(Uid::from_raw(uid))? {
+        Some(user) => user.name,
+        None => format!("#{}", uid),
+    };
+
+    Ok(ContainerInfo {
+        id: container.id().to_string(),
+        pid: container.state.pid,
+        status: container.status(),
+        bundle: container.state.bundle.clone(),
+        rootfs: container
+            .rootfs()
+            .map(|rootfs| rootfs.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        created: created.to_rfc3339(),
+        owner,
+        started: container
+            .started_at()
+            .map(|t| DateTime::<Local>::from(t).to_rfc3339()),
+        finished: container
+            .finished_at()
+            .map(|t| DateTime::<Local>::from(t).to_rfc3339()),
+        exit_code: container.exit_code(),
+        exit_signal: container.exit_signal(),
+        annotations: container.annotations().clone(),
+    })
+}
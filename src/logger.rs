@@ -6,70 +6,120 @@ use std::{
     str::FromStr,
 };
 
-use anyhow::Result;
-use log::{LevelFilter, Log, Metadata, Record};
+use anyhow::{bail, Result};
+use log::{Level, LevelFilter, Log, Metadata, Record};
 use once_cell::sync::OnceCell;
 use serde_json::json;
 
 pub static YOUKI_LOGGER: OnceCell<YoukiLogger> = OnceCell::new();
 pub static LOG_FILE: OnceCell<Option<File>> = OnceCell::new();
 
-pub fn init(container_id: &str, log_file: Option<PathBuf>) -> Result<()> {
-    let _log_file = LOG_FILE.get_or_init(|| -> Option<File> {
-        if let Ok(docker_root) = env::var("YOUKI_MODE") {
-            if let Some(log_file_path) = &log_file {
-                OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .truncate(false)
-                    .open(log_file_path)
-                    .expect("fail opening log file ");
-            };
+/// The level used when neither the flags nor YOUKI_LOG_LEVEL set one.
+const DEFAULT_LOG_LEVEL: LevelFilter = LevelFilter::Warn;
 
-            let mut log_file_path = PathBuf::from(&docker_root);
-            log_file_path.push(container_id);
-            log_file_path.push(format!("{}-json.log", container_id));
+/// How log records are written, following the text and JSON formats of runc
+/// that containerd parses from runtime logs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
 
-            let level_filter = if let Ok(log_level_str) = env::var("YOUKI_LOG_LEVEL") {
-                LevelFilter::from_str(&log_level_str).unwrap_or(LevelFilter::Warn)
-            } else {
-                LevelFilter::Warn
-            };
-            let logger = YOUKI_LOGGER.get_or_init(|| YoukiLogger::new(level_filter.to_level()));
-            log::set_logger(logger)
-                .map(|()| log::set_max_level(level_filter))
-                .unwrap();
-            Some(
-                OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .truncate(false)
-                    .open(log_file_path)
-                    .expect("fail opening log file"),
-            )
-        } else if let Some(log_file_path) = log_file {
-            Some(
-                OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .truncate(false)
-                    .open(log_file_path)
-                    .expect("fail opening log file"),
-            )
-        } else {
-            None
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => bail!("{} is not a valid log format", s),
         }
+    }
+}
+
+/// Sets up the logger for this process and every process it forks, which
+/// all write to the same log file. Without `level`, YOUKI_LOG_LEVEL is used.
+pub fn init(
+    container_id: Option<&str>,
+    log_file: Option<PathBuf>,
+    log_format: LogFormat,
+    level: Option<LevelFilter>,
+) -> Result<()> {
+    let log_file_path = log_file.or_else(|| {
+        // commands such as `list` are not tied to a single container
+        let docker_root = env::var("YOUKI_MODE").ok()?;
+        let container_id = container_id?;
+        let mut log_file_path = PathBuf::from(&docker_root);
+        log_file_path.push(container_id);
+        log_file_path.push(format!("{}-json.log", container_id));
+        Some(log_file_path)
+    });
+    let log_file = match log_file_path {
+        // Appending keeps the records of the forked processes from
+        // overwriting each other.
+        Some(log_file_path) => Some(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(log_file_path)?,
+        ),
+        None => None,
+    };
+    let _ = LOG_FILE.set(log_file);
+
+    let level_filter = level.unwrap_or_else(|| match env::var("YOUKI_LOG_LEVEL") {
+        Ok(log_level_str) => LevelFilter::from_str(&log_level_str).unwrap_or(DEFAULT_LOG_LEVEL),
+        Err(_) => DEFAULT_LOG_LEVEL,
     });
+    let logger = YOUKI_LOGGER.get_or_init(|| YoukiLogger::new(level_filter.to_level(), log_format));
+    if let Err(e) = log::set_logger(logger) {
+        bail!("failed to set the logger: {}", e);
+    }
+    log::set_max_level(level_filter);
     Ok(())
 }
 
+/// Returns true if log records end up on stderr, because the logger is set
+/// up without a log file.
+pub fn logs_to_stderr() -> bool {
+    YOUKI_LOGGER.get().is_some() && !matches!(LOG_FILE.get(), Some(Some(_)))
+}
+
 pub struct YoukiLogger {
     level: Option<log::Level>,
+    format: LogFormat,
 }
 
 impl YoukiLogger {
-    pub fn new(level: Option<log::Level>) -> Self {
-        Self { level }
+    pub fn new(level: Option<log::Level>, format: LogFormat) -> Self {
+        Self { level, format }
+    }
+
+    fn format(&self, record: &Record) -> String {
+        // the level names of logrus, which runc uses
+        let level = match record.level() {
+            Level::Error => "error",
+            Level::Warn => "warning",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        };
+        let msg = match (record.file(), record.line()) {
+            (Some(file), Some(line)) if self.level >= Some(Level::Debug) => {
+                format!("{} ({}:{})", record.args(), file, line)
+            }
+            _ => record.args().to_string(),
+        };
+        let time = chrono::Local::now().to_rfc3339();
+        match self.format {
+            LogFormat::Json => json!({
+                "level": level,
+                "msg": msg,
+                "time": time,
+            })
+            .to_string(),
+            LogFormat::Text => format!("time={:?} level={} msg={:?}", time, level, msg),
+        }
     }
 }
 
@@ -84,28 +134,19 @@ impl Log for YoukiLogger {
 
     fn log(&self, record: &Record) {
         if self.enabled(record.metadata()) {
-            let log_msg = match (record.file(), record.line()) {
-                (Some(file), Some(line)) => json!({
-                    "log": format!("[{} {}:{}] {}\r\n", record.level(), file, line, record.args()),
-                    "stream": "stdout",
-                    "time": chrono::Local::now().to_rfc3339()
-                }),
-                (_, _) => json!({
-                    "log": format!("[{}] {}\r\n", record.level(), record.args()),
-                    "stream": "stdout",
-                    "time": chrono::Local::now().to_rfc3339()
-                }),
-            };
-            if let Some(mut log_file) = LOG_FILE.get().unwrap().as_ref() {
-                let _ = writeln!(log_file, "{}", log_msg.to_string());
+            // a single write, so that the lines of concurrent processes
+            // don't interleave
+            let line = format!("{}\n", self.format(record));
+            if let Some(mut log_file) = LOG_FILE.get().and_then(Option::as_ref) {
+                let _ = log_file.write_all(line.as_bytes());
             } else {
-                let _ = writeln!(stderr(), "{}", log_msg.to_string());
+                let _ = stderr().write_all(line.as_bytes());
             }
         }
     }
 
     fn flush(&self) {
-        if let Some(mut log_file) = LOG_FILE.get().unwrap().as_ref() {
+        if let Some(mut log_file) = LOG_FILE.get().and_then(Option::as_ref) {
             log_file.flush().expect("Failed to flush");
         } else {
             stderr().flush().expect("Failed to flush");
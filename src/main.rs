@@ -1,25 +1,65 @@
-use std::env;
-use std::{path::PathBuf, fs};
+use std::{path::PathBuf, fs, process, str::FromStr};
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use env_logger;
+use log::LevelFilter;
 
 use rust_runtime::create;
+use rust_runtime::logger;
 use rust_runtime::start;
 use rust_runtime::delete;
 use rust_runtime::kill;
 use rust_runtime::stop;
 use rust_runtime::state;
+use rust_runtime::run;
+use rust_runtime::exec;
+use rust_runtime::list;
+use rust_runtime::ps;
+use rust_runtime::pause;
+use rust_runtime::resume;
+use rust_runtime::events;
+use rust_runtime::update;
+use rust_runtime::generate_spec;
+use rust_runtime::features;
+use rust_runtime::checkpoint;
+use rust_runtime::restore;
+use rust_runtime::rootless;
+use rust_runtime::validate;
+use rust_runtime::gc;
+use rust_runtime::stats;
 
 #[derive(Parser, Debug)]
 struct Opts {
-    #[clap(short, long, default_value = "/run/youki")]
-    root: PathBuf,
+    /// directory the state of containers is kept in, defaults to
+    /// /run/youki, or $XDG_RUNTIME_DIR/youki for rootless containers
+    #[clap(short, long)]
+    root: Option<PathBuf>,
     #[clap(short, long)]
     log: Option<PathBuf>,
+    /// format of the log records
+    #[clap(long, default_value = "text", value_parser = ["text", "json"])]
+    log_format: String,
+    /// enable debug logging, the same as --log-level debug
+    #[clap(long)]
+    debug: bool,
+    /// minimum level of the log records
+    #[clap(long, value_parser = ["error", "warn", "info", "debug", "trace"])]
+    log_level: Option<String>,
+    /// manage the cgroups of containers as transient systemd units, taking
+    /// cgroupsPath in the slice:prefix:name form
     #[clap(long)]
-    log_format: Option<String>,
+    systemd_cgroup: bool,
+    /// path to the criu binary used by checkpoint and restore
+    #[clap(long, default_value = "criu")]
+    criu: PathBuf,
+    /// whether containers are created rootless, auto when the runtime
+    /// doesn't run as root
+    #[clap(long, default_value = "auto", value_parser = ["true", "false", "auto"])]
+    rootless: String,
+    /// remove stale containers, as the gc command does, before running the
+    /// command
+    #[clap(long)]
+    gc: bool,
     #[clap(subcommand)]
     subcmd: SubCommand, 
 }
@@ -32,37 +72,122 @@ enum SubCommand {
     Kill(kill::Kill),
     Stop(stop::Stop),
     State(state::State),
+    Run(run::Run),
+    Exec(exec::Exec),
+    List(list::List),
+    Ps(ps::Ps),
+    Pause(pause::Pause),
+    Resume(resume::Resume),
+    Events(events::Events),
+    Update(update::Update),
+    Spec(generate_spec::GenerateSpec),
+    Features(features::Features),
+    Checkpoint(checkpoint::Checkpoint),
+    Restore(restore::Restore),
+    Validate(validate::Validate),
+    Gc(gc::Gc),
+    Stats(stats::Stats),
 }
 
 impl SubCommand {
-    fn get_container_id(&self) -> &String {
+    fn get_container_id(&self) -> Option<&String> {
         match &self {
-            SubCommand::Create(create) => &create.container_id,
-            SubCommand::Start(start) => &start.container_id,
-            SubCommand::Delete(delete) => &delete.container_id,
-            SubCommand::Kill(kill) => &kill.container_id,
-            SubCommand::Stop(stop) => &stop.container_id,
-            SubCommand::State(state) => &state.container_id,
+            SubCommand::Create(create) => Some(&create.container_id),
+            SubCommand::Start(start) => Some(&start.container_id),
+            SubCommand::Delete(delete) => Some(&delete.container_id),
+            SubCommand::Kill(kill) => Some(&kill.container_id),
+            SubCommand::Stop(stop) => Some(&stop.container_id),
+            SubCommand::State(state) => Some(&state.container_id),
+            SubCommand::Run(run) => Some(&run.create.container_id),
+            SubCommand::Exec(exec) => Some(&exec.container_id),
+            SubCommand::List(_) => None,
+            SubCommand::Ps(ps) => Some(&ps.container_id),
+            SubCommand::Pause(pause) => Some(&pause.container_id),
+            SubCommand::Resume(resume) => Some(&resume.container_id),
+            SubCommand::Events(events) => Some(&events.container_id),
+            SubCommand::Update(update) => Some(&update.container_id),
+            SubCommand::Spec(_) => None,
+            SubCommand::Features(_) => None,
+            SubCommand::Checkpoint(checkpoint) => Some(&checkpoint.container_id),
+            SubCommand::Restore(restore) => Some(&restore.container_id),
+            SubCommand::Validate(_) => None,
+            SubCommand::Gc(_) => None,
+            SubCommand::Stats(stats) => Some(&stats.container_id),
         }
     }
 }
 
-fn main() -> Result<()> {
-    env::set_var("RUST_LOG", "debug");
-    env_logger::init();
-    
+fn main() {
     let opts = Opts::parse();
-    rust_runtime::logger::init(opts.subcmd.get_container_id().as_str(), opts.log)?;
+    if let Err(e) = run(opts) {
+        // Like runc, the error is logged, which is where containerd looks
+        // for it, and printed unless the log already goes to stderr.
+        log::error!("{}", e);
+        if !logger::logs_to_stderr() {
+            eprintln!("{}", e);
+        }
+        process::exit(1);
+    }
+}
+
+fn run(opts: Opts) -> Result<()> {
+    let log_level = if opts.debug {
+        Some(LevelFilter::Debug)
+    } else {
+        opts.log_level.as_deref().and_then(|level| LevelFilter::from_str(level).ok())
+    };
+    logger::init(
+        opts.subcmd.get_container_id().map(String::as_str),
+        opts.log.clone(),
+        opts.log_format.parse()?,
+        log_level,
+    )?;
 
-    let root_path = PathBuf::from(&opts.root);
+    match opts.rootless.as_str() {
+        "true" => rootless::set_rootless(true),
+        "false" => rootless::set_rootless(false),
+        _ => {}
+    }
+    let root_path = opts.root.clone().unwrap_or_else(rootless::default_root);
     fs::create_dir_all(&root_path)?;
+    let root_path = fs::canonicalize(root_path)?;
+    if opts.gc {
+        gc::collect(&root_path)?;
+    }
 
     match opts.subcmd {
-        SubCommand::Create(create) => create.exec(root_path),
+        SubCommand::Create(mut create) => {
+            create.systemd_cgroup = opts.systemd_cgroup;
+            create.exec(root_path)
+        }
         SubCommand::Start(start) => start.exec(root_path),
         SubCommand::Delete(delete) => delete.exec(root_path),
         SubCommand::Kill(kill) => kill.exec(root_path),
         SubCommand::Stop(stop) => stop.exec(root_path),
         SubCommand::State(state) => state.exec(root_path),
+        SubCommand::Run(mut run) => {
+            run.create.systemd_cgroup = opts.systemd_cgroup;
+            run.exec(root_path)
+        }
+        SubCommand::Exec(exec) => exec.exec(root_path),
+        SubCommand::List(list) => list.exec(root_path),
+        SubCommand::Ps(ps) => ps.exec(root_path),
+        SubCommand::Pause(pause) => pause.exec(root_path),
+        SubCommand::Resume(resume) => resume.exec(root_path),
+        SubCommand::Events(events) => events.exec(root_path),
+        SubCommand::Update(update) => update.exec(root_path),
+        SubCommand::Spec(spec) => spec.exec(),
+        SubCommand::Features(features) => features.exec(),
+        SubCommand::Checkpoint(mut checkpoint) => {
+            checkpoint.criu_options.criu = opts.criu;
+            checkpoint.exec(root_path)
+        }
+        SubCommand::Restore(mut restore) => {
+            restore.criu_options.criu = opts.criu;
+            restore.exec(root_path)
+        }
+        SubCommand::Validate(validate) => validate.exec(),
+        SubCommand::Gc(gc) => gc.exec(root_path),
+        SubCommand::Stats(stats) => stats.exec(root_path),
     }
 }
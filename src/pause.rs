@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use clap::Parser;
+
+use crate::cgroups;
+use crate::container::{Container, ContainerStatus};
+
+/// Suspend every process in a container
+#[derive(Debug, Parser)]
+pub struct Pause {
+    pub container_id: String,
+}
+
+impl Pause {
+    pub fn exec(&self, root_path: PathBuf) -> Result<()> {
+        let container_root = root_path.join(&self.container_id);
+        if !container_root.exists() {
+            bail!("container {} does not exist", self.container_id)
+        }
+        let mut container = Container::load(container_root)?;
+        container.refresh_status()?;
+        if !container.can_pause() {
+            bail!(
+                "{} could not be paused because it was {:?}",
+                container.id(),
+                container.status()
+            )
+        }
+
+        let cgroup_path = match container.cgroup_path() {
+            Some(cgroup_path) => cgroup_path.to_path_buf(),
+            None => bail!("{} has no cgroup", container.id()),
+        };
+        cgroups::freeze(&cgroup_path)?;
+        container.set_status(ContainerStatus::Paused).save()?;
+
+        log::debug!("{} was paused.", self.container_id);
+        Ok(())
+    }
+}
@@ -40,15 +40,11 @@ impl ChildProcess {
             "child send to parent {:?}",
             (Message::ChildReady as u8).to_be_bytes()
         );
-        self.write_message_for_parent(Message::ChildReady)?;
-        self.sender_for_parent
-            .write_all(&(init_pid.as_raw()).to_be_bytes())?;
-        Ok(())
-    }
-
-    fn write_message_for_parent(&mut self, msg: Message) -> Result<()> {
-        self.sender_for_parent
-            .write_all(&(msg as u8).to_be_bytes())?;
+        // A single write, as the parent reads the pid as soon as the
+        // message arrives and the pipe doesn't block.
+        let mut buf = vec![Message::ChildReady as u8];
+        buf.extend_from_slice(&init_pid.as_raw().to_be_bytes());
+        self.sender_for_parent.write_all(&buf)?;
         Ok(())
     }
 
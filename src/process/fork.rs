@@ -1,21 +1,27 @@
 use std::fs;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 
 use anyhow::Result;
 use anyhow::bail;
+use chrono::Utc;
 use child::ChildProcess;
 use init::InitProcess;
 use nix::sched;
 use nix::sys::wait::{waitpid, WaitStatus};
 use nix::unistd;
+use nix::unistd::Pid;
 
+use crate::cgroups;
+use crate::cgroups::systemd;
 use crate::cond::Cond;
 use crate::container::Container;
 use crate::container::ContainerStatus;
 use crate::process::{child, init, parent, Process};
+use crate::rootless;
 use crate::spec;
+use crate::utils;
 
 pub fn fork_first<P: AsRef<Path>>(
     pid_file: Option<P>,
@@ -24,6 +30,8 @@ pub fn fork_first<P: AsRef<Path>>(
     container: &mut Container,
 ) -> Result<Process> {
     let ccond = Cond::new()?;
+    // signalled once the id mappings of the user namespace are written
+    let mcond = Cond::new()?;
 
     let (mut parent, sender_for_parent) = parent::ParentProcess::new()?;
     let child = child::ChildProcess::new(sender_for_parent)?;
@@ -47,23 +55,47 @@ pub fn fork_first<P: AsRef<Path>>(
                  * unsharing alll namespaces together results into incorrect namespace object.
                  */
                 if userns {
+                    // The parent writes our id mappings, which it can only
+                    // do as an unprivileged user while we are dumpable.
+                    prctl::set_dumpable(true).unwrap();
                     sched::unshare(sched::CloneFlags::CLONE_NEWUSER)?;
                 }
 
                 ccond.notify()?;
+                mcond.wait()?;
+                if userns {
+                    prctl::set_dumpable(false).unwrap();
+                }
 
                 Ok(Process::Child(child))
             }
             unistd::ForkResult::Parent { child } => {
                 ccond.wait()?;
+                if userns {
+                    rootless::write_id_mappings(child, linux)?;
+                }
+                mcond.notify()?;
+                parent.set_child_pid(child);
 
                 let init_pid = parent.wait_for_child_ready()?;
+                match setup_cgroup(linux, container, Pid::from_raw(init_pid)) {
+                    Ok(cgroup_path) => {
+                        container.set_cgroup_path(cgroup_path);
+                    }
+                    // An unprivileged user can only manage cgroups delegated
+                    // to them, so without one the container runs without.
+                    Err(e) if container.is_rootless() => {
+                        log::warn!("{} runs without a cgroup: {}", container.id(), e);
+                    }
+                    Err(e) => return Err(e),
+                }
                 container
                     .set_status(ContainerStatus::Created)
                     .set_pid(init_pid)
+                    .set_namespace_paths(Pid::from_raw(init_pid))
                     .save()?;
                 if let Some(pid_file) = pid_file {
-                    fs::write(&pid_file, format!("{}", child))?;
+                    utils::write_pid_file(pid_file.as_ref(), init_pid)?;
                 }
                 Ok(Process::Parent(parent))
             }
@@ -71,7 +103,51 @@ pub fn fork_first<P: AsRef<Path>>(
     }
 }
 
-pub fn fork_init(mut child_process: ChildProcess) -> Result<Process>  {
+/// Creates the cgroup of the container, moves `init_pid` into it and applies
+/// the resource limits of the spec, returning its path. With systemd, the
+/// unit's cgroup is systemd's: it moves the pid and applies the limits, and
+/// on v1 only the hierarchies it doesn't manage are joined.
+fn setup_cgroup(linux: &spec::Linux, container: &Container, init_pid: Pid) -> Result<PathBuf> {
+    if container.systemd_unit().is_some() {
+        let unit = systemd::Unit::parse(&linux.cgroups_path, container.id())?;
+        systemd::start_unit(&unit, init_pid, linux.resources.as_ref())?;
+        let cgroup_path = unit.cgroup_path()?;
+        if !cgroups::is_v2() {
+            cgroups::join_v1(&cgroup_path, init_pid)?;
+        }
+        return Ok(cgroup_path);
+    }
+
+    let cgroup_path = cgroups::path_of(&linux.cgroups_path, container.id());
+    cgroups::create(&cgroup_path, init_pid)?;
+    if let Some(resources) = &linux.resources {
+        cgroups::resources::apply(&cgroup_path, resources)?;
+    }
+    Ok(cgroup_path)
+}
+
+/// Forks the process that joins the namespaces of a running container on
+/// behalf of `exec`. Like the child of `fork_first`, it is expected to call
+/// `fork_init` to actually enter the PID namespace.
+pub fn fork_exec() -> Result<Process> {
+    let (mut parent, sender_for_parent) = parent::ParentProcess::new()?;
+    let child = child::ChildProcess::new(sender_for_parent)?;
+
+    unsafe {
+        match unistd::fork()? {
+            unistd::ForkResult::Child => Ok(Process::Child(child)),
+            unistd::ForkResult::Parent { child } => {
+                parent.set_child_pid(child);
+                Ok(Process::Parent(parent))
+            }
+        }
+    }
+}
+
+/// Forks the init process. The calling process stays as its parent and exits
+/// with the init's exit status, which is also recorded in the state of the
+/// container at `container_root` when one is given.
+pub fn fork_init(mut child_process: ChildProcess, container_root: Option<&Path>) -> Result<Process> {
     let sender_for_child = child_process.setup_uds()?;
     unsafe {
         match unistd::fork()? {
@@ -80,17 +156,29 @@ pub fn fork_init(mut child_process: ChildProcess) -> Result<Process>  {
                 child_process.wait_for_init_ready()?;
                 child_process.ready(child)?;
 
-                match waitpid(child, None)? {
+                let (exit_code, exit_signal) = match waitpid(child, None)? {
                     WaitStatus::Exited(pid, status) => {
                         log::debug!("exited pid: {:?}, status: {:?}", pid, status);
-                        exit(status);
+                        (status, None)
                     }
                     WaitStatus::Signaled(pid, status, _) => {
                         log::debug!("signaled pid: {:?}, status: {:?}", pid, status);
-                        exit(0);
+                        (128 + status as i32, Some(status as i32))
                     }
                     _ => bail!("abnormal exited!"),
+                };
+                if let Some(container_root) = container_root {
+                    let mut container = Container::load(container_root.to_path_buf())?;
+                    container
+                        .set_status(ContainerStatus::Stopped)
+                        .set_exit_code(exit_code)
+                        .set_finished_at(Utc::now());
+                    if let Some(exit_signal) = exit_signal {
+                        container.set_exit_signal(exit_signal);
+                    }
+                    container.save()?;
                 }
+                exit(exit_code);
             }
         }
     }
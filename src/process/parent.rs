@@ -5,6 +5,7 @@ use anyhow::{Result, bail};
 use mio::unix::pipe;
 use mio::unix::pipe::{Receiver, Sender};
 use mio::{Poll, Interest, Token, Events};
+use nix::unistd::Pid;
 
 use super::message::Message;
 
@@ -13,6 +14,7 @@ const PARENT: Token = Token(0);
 pub struct ParentProcess {
     receiver: Receiver,
     poll: Poll,
+    child_pid: Option<Pid>,
 }
 
 impl ParentProcess {
@@ -21,7 +23,25 @@ impl ParentProcess {
         let poll = Poll::new()?;
         poll.registry()
             .register(&mut receiver, PARENT, Interest::READABLE)?;
-        Ok((Self { receiver, poll }, sender))
+        Ok((
+            Self {
+                receiver,
+                poll,
+                child_pid: None,
+            },
+            sender,
+        ))
+    }
+
+    pub fn set_child_pid(&mut self, pid: Pid) -> &mut Self {
+        self.child_pid = Some(pid);
+        self
+    }
+
+    /// The pid of the intermediate process, which exits with the exit
+    /// status of the container's init.
+    pub fn child_pid(&self) -> Option<Pid> {
+        self.child_pid
     }
 
     pub fn wait_for_child_ready(&mut self) -> Result<i32> {
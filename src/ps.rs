@@ -0,0 +1,122 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local};
+use clap::Parser;
+use nix::unistd::{Pid, Uid, User};
+use procfs::process::{all_processes, Process};
+
+use crate::cgroups;
+use crate::container::{Container, ContainerStatus};
+use crate::utils;
+
+/// Display the processes running inside a container
+#[derive(Debug, Parser)]
+pub struct Ps {
+    pub container_id: String,
+    /// output format, json prints the host PIDs only
+    #[clap(short, long, default_value = "table", value_parser = ["table", "json"])]
+    pub format: String,
+}
+
+impl Ps {
+    pub fn exec(&self, root_path: PathBuf) -> Result<()> {
+        let container_root = root_path.join(&self.container_id);
+        if !container_root.exists() {
+            bail!("container {} does not exist", self.container_id)
+        }
+        let mut container = Container::load(container_root)?;
+        container.refresh_status()?;
+        if let ContainerStatus::Stopped = container.status() {
+            bail!("{} is not running", container.id())
+        }
+
+        let pids = container_pids(&container)?;
+        if self.format == "json" {
+            let pids: Vec<i32> = pids.iter().map(|pid| pid.as_raw()).collect();
+            println!("{}", serde_json::to_string(&pids)?);
+            return Ok(());
+        }
+
+        let mut rows = Vec::new();
+        for pid in pids {
+            // the process may have exited since the list was taken
+            if let Ok(process) = Process::new(pid.as_raw()) {
+                rows.push(process_row(&process)?);
+            }
+        }
+        utils::print_table(
+            &["UID", "PID", "PPID", "STIME", "TTY", "TIME", "CMD"],
+            &rows,
+        );
+        Ok(())
+    }
+}
+
+/// Returns the host PIDs of every process in the container, sorted.
+pub fn container_pids(container: &Container) -> Result<Vec<Pid>> {
+    let mut pids = match container.cgroup_path() {
+        Some(cgroup_path) if cgroups::exists(cgroup_path)? => cgroups::pids(cgroup_path)?,
+        _ => pid_namespace_pids(container)?,
+    };
+    pids.sort();
+    Ok(pids)
+}
+
+/// Returns the processes sharing the PID namespace of the container's init,
+/// for containers without a cgroup.
+fn pid_namespace_pids(container: &Container) -> Result<Vec<Pid>> {
+    let pid_ns = fs::read_link(format!("/proc/{}/ns/pid", container.pid().unwrap()))?;
+    if pid_ns == fs::read_link("/proc/self/ns/pid")? {
+        bail!(
+            "{} has no cgroup and shares the PID namespace of the host, so its processes can't be told apart",
+            container.id()
+        );
+    }
+    let pids = all_processes()?
+        .into_iter()
+        .filter(|p| {
+            fs::read_link(format!("/proc/{}/ns/pid", p.pid))
+                .map(|ns| ns == pid_ns)
+                .unwrap_or(false)
+        })
+        .map(|p| Pid::from_raw(p.pid))
+        .collect();
+    Ok(pids)
+}
+
+fn process_row(process: &Process) -> Result<Vec<String>> {
+    let stat = &process.stat;
+    let user = match User::from_uid(Uid::from_raw(process.owner))? {
+        Some(user) => user.name,
+        None => process.owner.to_string(),
+    };
+    let stime: DateTime<Local> = stat.starttime()?;
+    let tty = match stat.tty_nr() {
+        (0, 0) => "?".to_string(),
+        (136..=143, minor) => format!("pts/{}", minor),
+        (major, minor) => format!("{}:{}", major, minor),
+    };
+    let cpu_secs = (stat.utime + stat.stime) / procfs::ticks_per_second()? as u64;
+    let time = format!(
+        "{:02}:{:02}:{:02}",
+        cpu_secs / 3600,
+        cpu_secs % 3600 / 60,
+        cpu_secs % 60
+    );
+    let cmd = match process.cmdline() {
+        Ok(cmdline) if !cmdline.is_empty() => cmdline.join(" "),
+        _ => format!("[{}]", stat.comm),
+    };
+
+    Ok(vec![
+        user,
+        stat.pid.to_string(),
+        stat.ppid.to_string(),
+        stime.format("%H:%M").to_string(),
+        tty,
+        time,
+        cmd,
+    ])
+}
@@ -0,0 +1,248 @@
+use std::fs;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use chrono::Utc;
+use clap::Parser;
+use nix::errno::Errno;
+use nix::mount::{self, MntFlags, MsFlags};
+use nix::unistd::{self, Pid};
+
+use crate::cgroups;
+use crate::container::{Container, ContainerStatus};
+use crate::criu::{self, CriuOptions};
+use crate::delete;
+use crate::spec::Spec;
+use crate::tty;
+use crate::utils;
+
+/// The directory the rootfs is bind mounted to while CRIU restores into it.
+const CRIU_ROOT: &str = "criu-root";
+const RESTORE_PID_FILE: &str = "restore.pid";
+
+/// Restore a container from an image directory written by `checkpoint`
+#[derive(Debug, Parser)]
+pub struct Restore {
+    pub container_id: String,
+    /// directory the images are read from
+    #[clap(long)]
+    pub image_path: PathBuf,
+    /// directory for the logs of CRIU, defaults to --image-path
+    #[clap(long)]
+    pub work_path: Option<PathBuf>,
+    #[clap(short, long, default_value = ".")]
+    pub bundle: PathBuf,
+    /// socket the new console is sent to, if the container had a terminal
+    #[clap(long)]
+    pub console_socket: Option<PathBuf>,
+    #[clap(long)]
+    pub pid_file: Option<PathBuf>,
+    /// accepted for runc compatibility, restore always returns once the
+    /// container is restored
+    #[clap(short, long)]
+    pub detach: bool,
+    /// accepted for runc compatibility, the rootfs is never pivoted into on
+    /// restore
+    #[clap(long)]
+    pub no_pivot: bool,
+    /// accepted for runc compatibility, the runtime never becomes the
+    /// subreaper of the restored process
+    #[clap(long)]
+    pub no_subreaper: bool,
+    #[clap(flatten)]
+    pub criu_options: CriuOptions,
+}
+
+impl Restore {
+    pub fn exec(&self, root_path: PathBuf) -> Result<()> {
+        log::debug!("{} is being restored...", self.container_id);
+        let container_dir = root_path.join(&self.container_id);
+        if container_dir.exists() {
+            bail!("{} already exists", self.container_id);
+        }
+        let image_path = fs::canonicalize(&self.image_path)?;
+        let work_path = match &self.work_path {
+            Some(work_path) => {
+                fs::create_dir_all(work_path)?;
+                fs::canonicalize(work_path)?
+            }
+            None => image_path.clone(),
+        };
+        let bundle = fs::canonicalize(&self.bundle)?;
+        let spec = Spec::load(bundle.join("config.json"))?;
+
+        fs::create_dir(&container_dir)?;
+        let container_dir = fs::canonicalize(container_dir)?;
+        let mut container = Container::new(
+            &self.container_id,
+            ContainerStatus::Creating,
+            None,
+            bundle.to_str().unwrap(),
+            &container_dir,
+        )?;
+        container
+            .set_annotations(spec.annotations.clone())
+            .set_rootfs(spec.root.path.clone())
+            .set_creator(unistd::getpid())
+            .save()?;
+
+        let result = self.restore(&spec, &image_path, &work_path, &mut container);
+        if result.is_err() {
+            // The directory is only removed once nothing is mounted in it,
+            // as removing it would otherwise reach into the mounts.
+            match delete::unmount_all(&container_dir) {
+                Ok(()) => {
+                    let _ = fs::remove_dir_all(&container_dir);
+                }
+                Err(e) => log::warn!(
+                    "{:?} is left behind as it can't be unmounted: {}",
+                    container_dir,
+                    e
+                ),
+            }
+        }
+        result
+    }
+
+    fn restore(
+        &self,
+        spec: &Spec,
+        image_path: &Path,
+        work_path: &Path,
+        container: &mut Container,
+    ) -> Result<()> {
+        let linux = spec.linux.as_ref().unwrap();
+        let cgroup_path = cgroups::path_of(&linux.cgroups_path, container.id());
+        let pid_file = container.root.join(RESTORE_PID_FILE);
+
+        // CRIU needs a mount point to restore the root of the container on.
+        let criu_root = CriuRoot::mount(&spec.root.path, container.root.join(CRIU_ROOT))?;
+
+        let mut args = vec![
+            "--images-dir".to_string(),
+            image_path.to_string_lossy().to_string(),
+            "--restore-detached".to_string(),
+            "--pidfile".to_string(),
+            pid_file.to_string_lossy().to_string(),
+            "--root".to_string(),
+            criu_root.path.to_string_lossy().to_string(),
+            "--cgroup-root".to_string(),
+            cgroup_path.to_string_lossy().to_string(),
+        ];
+        args.extend(self.criu_options.args());
+        args.extend(criu::external_mounts(spec, true));
+        let (namespace_args, mut fds) = criu::external_namespaces(spec, true)?;
+        args.extend(namespace_args);
+        let (descriptor_args, console_fd) = self.inherit_descriptors(image_path)?;
+        args.extend(descriptor_args);
+        fds.extend(console_fd);
+
+        let result = criu::run(&self.criu_options.criu, "restore", &args, work_path);
+        for fd in fds {
+            unistd::close(fd)?;
+        }
+        criu_root.unmount()?;
+        result?;
+
+        let pid: i32 = fs::read_to_string(&pid_file)?.trim().parse()?;
+        fs::remove_file(&pid_file)?;
+        if let Some(pid_file) = &self.pid_file {
+            utils::write_pid_file(pid_file, pid)?;
+        }
+        container
+            .set_status(ContainerStatus::Running)
+            .set_started_at(Utc::now())
+            .set_pid(pid)
+            .set_cgroup_path(cgroup_path)
+            .set_namespace_paths(Pid::from_raw(pid))
+            .save()?;
+
+        log::debug!("{} was restored as {}.", self.container_id, pid);
+        Ok(())
+    }
+
+    /// Returns the arguments that hand the standard streams of the restored
+    /// process over to CRIU. Pipes are connected to our own, and a terminal
+    /// is replaced by a new one sent to the console socket, whose slave is
+    /// returned to be closed once CRIU exits.
+    fn inherit_descriptors(&self, image_path: &Path) -> Result<(Vec<String>, Option<RawFd>)> {
+        let mut args = Vec::new();
+        let mut console = None;
+        for (fd, descriptor) in criu::load_descriptors(image_path)?.iter().enumerate() {
+            if descriptor.starts_with("tty[") {
+                // the streams all share the same terminal
+                if console.is_some() {
+                    continue;
+                }
+                let console_socket = match &self.console_socket {
+                    Some(console_socket) => console_socket,
+                    None => bail!("--console-socket is required to restore a terminal"),
+                };
+                let csocketfd = tty::connect_console_socket(console_socket)?;
+                let slave = tty::send_console(&csocketfd)?;
+                unistd::close(csocketfd.as_raw_fd())?;
+                console = Some(slave);
+                args.push("--inherit-fd".to_string());
+                args.push(format!("fd[{}]:{}", slave, descriptor));
+            } else if descriptor.starts_with("pipe:") {
+                args.push("--inherit-fd".to_string());
+                args.push(format!("fd[{}]:{}", fd, descriptor));
+            }
+        }
+        Ok((args, console))
+    }
+}
+
+/// The rootfs bind mounted on the CRIU root. It is unmounted and removed
+/// when dropped, so that no error leaves the rootfs mounted in the
+/// container directory.
+struct CriuRoot {
+    path: PathBuf,
+    released: bool,
+}
+
+impl CriuRoot {
+    fn mount(rootfs: &Path, path: PathBuf) -> Result<Self> {
+        fs::create_dir(&path)?;
+        let criu_root = Self {
+            path,
+            released: false,
+        };
+        mount::mount(
+            Some(rootfs),
+            &criu_root.path,
+            None::<&str>,
+            MsFlags::MS_BIND | MsFlags::MS_REC,
+            None::<&str>,
+        )?;
+        Ok(criu_root)
+    }
+
+    /// Unmounts the rootfs, reporting the errors the drop only warns about.
+    fn unmount(mut self) -> Result<()> {
+        self.released = true;
+        self.release()
+    }
+
+    fn release(&self) -> Result<()> {
+        match mount::umount2(&self.path, MntFlags::MNT_DETACH) {
+            // the mount failed in the first place
+            Err(nix::Error::Sys(Errno::EINVAL)) => {}
+            result => result?,
+        }
+        fs::remove_dir(&self.path)?;
+        Ok(())
+    }
+}
+
+impl Drop for CriuRoot {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        if let Err(e) = self.release() {
+            log::warn!("failed to unmount {:?}: {}", self.path, e);
+        }
+    }
+}
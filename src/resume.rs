@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use clap::Parser;
+
+use crate::cgroups;
+use crate::container::{Container, ContainerStatus};
+
+/// Resume every process in a paused container
+#[derive(Debug, Parser)]
+pub struct Resume {
+    pub container_id: String,
+}
+
+impl Resume {
+    pub fn exec(&self, root_path: PathBuf) -> Result<()> {
+        let container_root = root_path.join(&self.container_id);
+        if !container_root.exists() {
+            bail!("container {} does not exist", self.container_id)
+        }
+        let mut container = Container::load(container_root)?;
+        container.refresh_status()?;
+        if !container.can_resume() {
+            bail!(
+                "{} could not be resumed because it was {:?}",
+                container.id(),
+                container.status()
+            )
+        }
+
+        let cgroup_path = match container.cgroup_path() {
+            Some(cgroup_path) => cgroup_path.to_path_buf(),
+            None => bail!("{} has no cgroup", container.id()),
+        };
+        cgroups::thaw(&cgroup_path)?;
+        container.set_status(ContainerStatus::Running).save()?;
+
+        log::debug!("{} was resumed.", self.container_id);
+        Ok(())
+    }
+}
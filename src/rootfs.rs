@@ -11,9 +11,10 @@ use nix::mount::MsFlags;
 use nix::mount::*;
 use nix::sys::stat::{mknod, umask};
 use nix::sys::stat::{Mode, SFlag};
-use nix::unistd::{chdir, chown, close, fchdir, getcwd, pivot_root};
+use nix::unistd::{chdir, chown, chroot, close, fchdir, getcwd, pivot_root};
 use nix::unistd::{Gid, Uid};
 use nix::NixPath;
+use procfs::process::Process;
 
 use crate::spec::{LinuxDevice, LinuxDeviceType, Mount, Spec};
 
@@ -307,46 +308,110 @@ pub fn pivot_rootfs<P: ?Sized + NixPath>(path: &P) -> Result<()> {
     Ok(())
 }
 
+/// Moves `path` over / and chroots into it, for when pivot_root(2) fails,
+/// e.g. on a rootfs in ramfs. Unlike pivot_root, this leaves the old root
+/// mounted in the namespace, out of sight of the chroot but not out of reach
+/// of a process that can escape one.
+pub fn move_rootfs(path: &Path) -> Result<()> {
+    chdir(path)?;
+
+    // The kernel only lets a mount namespace mount a new procfs or sysfs if
+    // it already has a full one, and the host's would still count after the
+    // chroot, so they are unmounted, as runc does.
+    for m in Process::myself()?.mountinfo()? {
+        if m.root != "/" || (m.fs_type != "proc" && m.fs_type != "sysfs") {
+            continue;
+        }
+        if m.mount_point.starts_with(path) {
+            continue;
+        }
+        // keep the unmount from propagating to the host
+        match mount(
+            None::<&str>,
+            &m.mount_point,
+            None::<&str>,
+            MsFlags::MS_SLAVE | MsFlags::MS_REC,
+            None::<&str>,
+        ) {
+            // below a mount that has already been unmounted
+            Err(nix::Error::Sys(Errno::ENOENT)) => continue,
+            result => result?,
+        }
+        log::debug!("unmount {:?} before moving the rootfs", m.mount_point);
+        match umount2(&m.mount_point, MntFlags::MNT_DETACH) {
+            // Mounts inherited by a user namespace are locked, so they are
+            // covered instead.
+            Err(nix::Error::Sys(Errno::EINVAL)) | Err(nix::Error::Sys(Errno::EPERM)) => {
+                mount(
+                    Some("tmpfs"),
+                    &m.mount_point,
+                    Some("tmpfs"),
+                    MsFlags::empty(),
+                    None::<&str>,
+                )?;
+            }
+            result => result?,
+        }
+    }
+
+    mount(Some(path), "/", None::<&str>, MsFlags::MS_MOVE, None::<&str>)?;
+    chroot(".")?;
+    chdir("/")?;
+    Ok(())
+}
+
+/// Options of a mount that map to mount flags, with whether the option
+/// clears the flag rather than sets it.
+fn mount_options() -> Vec<(&'static str, bool, MsFlags)> {
+    vec![
+        ("defaults", false, MsFlags::empty()),
+        ("ro", false, MsFlags::MS_RDONLY),
+        ("rw", true, MsFlags::MS_RDONLY),
+        ("suid", true, MsFlags::MS_NOSUID),
+        ("nosuid", false, MsFlags::MS_NOSUID),
+        ("dev", true, MsFlags::MS_NODEV),
+        ("nodev", false, MsFlags::MS_NODEV),
+        ("exec", true, MsFlags::MS_NOEXEC),
+        ("noexec", false, MsFlags::MS_NOEXEC),
+        ("sync", false, MsFlags::MS_SYNCHRONOUS),
+        ("async", true, MsFlags::MS_SYNCHRONOUS),
+        ("dirsync", false, MsFlags::MS_DIRSYNC),
+        ("remount", false, MsFlags::MS_REMOUNT),
+        ("mand", false, MsFlags::MS_MANDLOCK),
+        ("nomand", true, MsFlags::MS_MANDLOCK),
+        ("atime", true, MsFlags::MS_NOATIME),
+        ("noatime", false, MsFlags::MS_NOATIME),
+        ("diratime", true, MsFlags::MS_NODIRATIME),
+        ("nodiratime", false, MsFlags::MS_NODIRATIME),
+        ("bind", false, MsFlags::MS_BIND),
+        ("rbind", false, MsFlags::MS_BIND | MsFlags::MS_REC),
+        ("unbindable", false, MsFlags::MS_UNBINDABLE),
+        ("runbindable", false, MsFlags::MS_UNBINDABLE | MsFlags::MS_REC),
+        ("private", false, MsFlags::MS_PRIVATE),
+        ("rprivate", false, MsFlags::MS_PRIVATE | MsFlags::MS_REC),
+        ("shared", false, MsFlags::MS_SHARED),
+        ("rshared", false, MsFlags::MS_SHARED | MsFlags::MS_REC),
+        ("slave", false, MsFlags::MS_SLAVE),
+        ("rslave", false, MsFlags::MS_SLAVE | MsFlags::MS_REC),
+        ("relatime", false, MsFlags::MS_RELATIME),
+        ("norelatime", true, MsFlags::MS_RELATIME),
+        ("strictatime", false, MsFlags::MS_STRICTATIME),
+        ("nostrictatime", true, MsFlags::MS_STRICTATIME),
+    ]
+}
+
+/// Returns the mount options that are turned into mount flags. Any other
+/// option is passed to the filesystem as data.
+pub fn supported_mount_options() -> Vec<&'static str> {
+    mount_options().into_iter().map(|(name, _, _)| name).collect()
+}
+
 fn parse_mount(m: &Mount) -> (MsFlags, String) {
+    let options = mount_options();
     let mut flags = MsFlags::empty();
     let mut data = Vec::new();
     for s in &m.options {
-        if let Some((is_clear, flag)) = match s.as_str() {
-            "defaults" => Some((false, MsFlags::empty())),
-            "ro" => Some((false, MsFlags::MS_RDONLY)),
-            "rw" => Some((true, MsFlags::MS_RDONLY)),
-            "suid" => Some((true, MsFlags::MS_NOSUID)),
-            "nosuid" => Some((false, MsFlags::MS_NOSUID)),
-            "dev" => Some((true, MsFlags::MS_NODEV)),
-            "nodev" => Some((false, MsFlags::MS_NODEV)),
-            "exec" => Some((true, MsFlags::MS_NOEXEC)),
-            "noexec" => Some((false, MsFlags::MS_NOEXEC)),
-            "sync" => Some((false, MsFlags::MS_SYNCHRONOUS)),
-            "async" => Some((true, MsFlags::MS_SYNCHRONOUS)),
-            "dirsync" => Some((false, MsFlags::MS_DIRSYNC)),
-            "remount" => Some((false, MsFlags::MS_REMOUNT)),
-            "mand" => Some((false, MsFlags::MS_MANDLOCK)),
-            "nomand" => Some((true, MsFlags::MS_MANDLOCK)),
-            "atime" => Some((true, MsFlags::MS_NOATIME)),
-            "noatime" => Some((false, MsFlags::MS_NOATIME)),
-            "diratime" => Some((true, MsFlags::MS_NODIRATIME)),
-            "nodiratime" => Some((false, MsFlags::MS_NODIRATIME)),
-            "bind" => Some((false, MsFlags::MS_BIND)),
-            "rbind" => Some((false, MsFlags::MS_BIND | MsFlags::MS_REC)),
-            "unbindable" => Some((false, MsFlags::MS_UNBINDABLE)),
-            "runbindable" => Some((false, MsFlags::MS_UNBINDABLE | MsFlags::MS_REC)),
-            "private" => Some((false, MsFlags::MS_PRIVATE)),
-            "rprivate" => Some((false, MsFlags::MS_PRIVATE | MsFlags::MS_REC)),
-            "shared" => Some((false, MsFlags::MS_SHARED)),
-            "rshared" => Some((false, MsFlags::MS_SHARED | MsFlags::MS_REC)),
-            "slave" => Some((false, MsFlags::MS_SLAVE)),
-            "rslave" => Some((false, MsFlags::MS_SLAVE | MsFlags::MS_REC)),
-            "relatime" => Some((false, MsFlags::MS_RELATIME)),
-            "norelatime" => Some((true, MsFlags::MS_RELATIME)),
-            "strictatime" => Some((false, MsFlags::MS_STRICTATIME)),
-            "nostrictatime" => Some((true, MsFlags::MS_STRICTATIME)),
-            _ => None,
-        } {
+        if let Some(&(_, is_clear, flag)) = options.iter().find(|(name, _, _)| name == s) {
             if is_clear {
                 flags &= !flag;
             } else {
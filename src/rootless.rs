@@ -0,0 +1,138 @@
+//! Support for containers created by an unprivileged user, which can only
+//! be root inside a user namespace of their own.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::{bail, Result};
+use nix::unistd::{self, Pid};
+use once_cell::sync::OnceCell;
+
+use crate::spec::{Linux, LinuxIDMapping, LinuxNamespaceType, Spec};
+
+const DEFAULT_ROOT: &str = "/run/youki";
+
+/// The mode set by the global --rootless flag, if not left to auto.
+static ROOTLESS: OnceCell<bool> = OnceCell::new();
+
+/// Overrides whether containers are created rootless, as runc's
+/// `--rootless true|false` does, e.g. for root in a user namespace that
+/// lacks the privileges of the host's root.
+pub fn set_rootless(rootless: bool) {
+    let _ = ROOTLESS.set(rootless);
+}
+
+/// Returns true if containers are created rootless, which by default is
+/// whenever the runtime doesn't run as root.
+pub fn is_rootless() -> bool {
+    ROOTLESS
+        .get()
+        .copied()
+        .unwrap_or_else(|| !unistd::geteuid().is_root())
+}
+
+/// Returns the directory the state of containers is kept in by default.
+/// /run is only writable by root, so rootless containers keep theirs in
+/// $XDG_RUNTIME_DIR, which belongs to the user.
+pub fn default_root() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if is_rootless() && !dir.is_empty() => PathBuf::from(dir).join("youki"),
+        _ => PathBuf::from(DEFAULT_ROOT),
+    }
+}
+
+/// Checks that a rootless container can be created from `spec`: it needs
+/// a user namespace, and ids mapped into it for the runtime to become root.
+pub fn validate(spec: &Spec) -> Result<()> {
+    let linux = match &spec.linux {
+        Some(linux) => linux,
+        None => bail!("rootless containers require the linux section of the spec"),
+    };
+    if !linux
+        .namespaces
+        .iter()
+        .any(|ns| matches!(ns.typ, LinuxNamespaceType::User))
+    {
+        bail!("rootless containers require a user namespace, see `spec --rootless`");
+    }
+    if linux.uid_mappings.is_empty() || linux.gid_mappings.is_empty() {
+        bail!("rootless containers require uidMappings and gidMappings");
+    }
+    Ok(())
+}
+
+/// Writes the uid and gid mappings of `linux` for the new user namespace of
+/// `pid`. Root may map any ids. Without privileges, the kernel only accepts
+/// a single id mapped to the user's own, which is written directly; larger
+/// mappings are left to the setuid newuidmap and newgidmap of shadow-utils,
+/// which check them against /etc/subuid and /etc/subgid.
+pub fn write_id_mappings(pid: Pid, linux: &Linux) -> Result<()> {
+    // what the kernel accepts depends on the privileges of the runtime,
+    // whatever the mode
+    let rootless = !unistd::geteuid().is_root();
+    let uid = unistd::geteuid().as_raw();
+    let gid = unistd::getegid().as_raw();
+
+    if rootless && !is_own_id(&linux.uid_mappings, uid) {
+        run_id_map_helper("newuidmap", pid, &linux.uid_mappings)?;
+    } else {
+        write_id_mapping(pid, "uid_map", &linux.uid_mappings)?;
+    }
+
+    if rootless && !is_own_id(&linux.gid_mappings, gid) {
+        run_id_map_helper("newgidmap", pid, &linux.gid_mappings)?;
+    } else {
+        if rootless {
+            // The kernel refuses a gid_map from an unprivileged user until
+            // setgroups(2) is disabled, since dropping groups could grant
+            // access that a negative group permission denied.
+            fs::write(format!("/proc/{}/setgroups", pid), "deny")?;
+        }
+        write_id_mapping(pid, "gid_map", &linux.gid_mappings)?;
+    }
+    Ok(())
+}
+
+/// Returns true if `mappings` map a single id to `id` on the host.
+fn is_own_id(mappings: &[LinuxIDMapping], id: u32) -> bool {
+    matches!(mappings, [m] if m.host_id == id && m.size == 1)
+}
+
+fn write_id_mapping(pid: Pid, file: &str, mappings: &[LinuxIDMapping]) -> Result<()> {
+    let content: String = mappings
+        .iter()
+        .map(|m| format!("{} {} {}\n", m.container_id, m.host_id, m.size))
+        .collect();
+    log::debug!("write {:?} to /proc/{}/{}", content, pid, file);
+    if let Err(e) = fs::write(format!("/proc/{}/{}", pid, file), &content) {
+        bail!("failed to write {} of {}: {}", file, pid, e);
+    }
+    Ok(())
+}
+
+fn run_id_map_helper(helper: &str, pid: Pid, mappings: &[LinuxIDMapping]) -> Result<()> {
+    let mut command = Command::new(helper);
+    command.arg(pid.to_string());
+    for m in mappings {
+        command.args(&[
+            m.container_id.to_string(),
+            m.host_id.to_string(),
+            m.size.to_string(),
+        ]);
+    }
+    log::debug!("{:?}", command);
+    let status = match command.status() {
+        Ok(status) => status,
+        Err(e) => bail!(
+            "failed to run {}, which is needed to map more than the user's own id: {}",
+            helper,
+            e
+        ),
+    };
+    if !status.success() {
+        bail!("{} failed with {}", helper, status);
+    }
+    Ok(())
+}
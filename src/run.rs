@@ -0,0 +1,73 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use nix::sys::wait::{waitpid, WaitStatus};
+
+use crate::create::Create;
+use crate::delete::Delete;
+use crate::process::Process;
+use crate::start::Start;
+
+/// Create a container and start it in one step
+#[derive(Debug, Parser)]
+pub struct Run {
+    #[clap(flatten)]
+    pub create: Create,
+    /// detach from the container's process and return once it has started
+    #[clap(short, long)]
+    pub detach: bool,
+    /// delete the container automatically after it exits
+    #[clap(long)]
+    pub rm: bool,
+    /// keep the container after it exits, which is the default without
+    /// --rm
+    #[clap(long, conflicts_with = "rm")]
+    pub keep: bool,
+}
+
+impl Run {
+    pub fn exec(&self, root_path: PathBuf) -> Result<()> {
+        if self.detach && self.rm {
+            bail!("--detach and --rm cannot be used together");
+        }
+
+        let container_id = &self.create.container_id;
+        let parent = match self.create.create(root_path.clone())? {
+            Process::Parent(parent) => parent,
+            // Only the parent returns here; the forked processes either exec
+            // the container's command or exit on their own.
+            _ => return Ok(()),
+        };
+
+        Start {
+            container_id: container_id.clone(),
+        }
+        .exec(root_path.clone())?;
+
+        if self.detach {
+            log::debug!("{} is running detached", container_id);
+            return Ok(());
+        }
+
+        let child_pid = parent
+            .child_pid()
+            .expect("the intermediate process should have been forked.");
+        let exit_code = match waitpid(child_pid, None)? {
+            WaitStatus::Exited(_, status) => status,
+            WaitStatus::Signaled(_, sig, _) => 128 + sig as i32,
+            status => bail!("unexpected wait status {:?}", status),
+        };
+        log::debug!("{} exited with {}", container_id, exit_code);
+
+        if self.rm {
+            Delete {
+                container_id: container_id.clone(),
+                force: false,
+            }
+            .exec(root_path)?;
+        }
+
+        std::process::exit(exit_code)
+    }
+}
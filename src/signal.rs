@@ -1,8 +1,16 @@
 use anyhow::{bail, Result};
+use nix::errno::Errno;
 use nix::sys::signal::Signal;
+use nix::unistd::Pid;
 
-pub fn from_str(signal: &str) -> Result<Signal> {
+/// Parses a signal given by name, with or without the SIG prefix, or by
+/// number. It returns the raw signal number, since real-time signals can't
+/// be represented by `Signal`.
+pub fn from_str(signal: &str) -> Result<libc::c_int> {
     use Signal::*;
+    if let Some(sig) = real_time_from_str(signal)? {
+        return Ok(sig);
+    }
     Ok(match signal.to_ascii_uppercase().as_str() {
         "1" | "HUP" | "SIGHUP" => Signal::SIGHUP,
         "2" | "INT" | "SIGINT" => Signal::SIGINT,
@@ -36,5 +44,43 @@ pub fn from_str(signal: &str) -> Result<Signal> {
         "30" | "PWR" | "SIGPWR" => SIGPWR,
         "31" | "SYS" | "SIGSYS" => SIGSYS,
         _ => bail! {"{} is not a valid signal", signal},
-    })
+    } as libc::c_int)
+}
+
+/// Parses the RTMIN+n and RTMAX-n forms of real-time signals, as well as
+/// their numbers.
+fn real_time_from_str(signal: &str) -> Result<Option<libc::c_int>> {
+    let (min, max) = (libc::SIGRTMIN(), libc::SIGRTMAX());
+    let upper = signal.to_ascii_uppercase();
+    let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+    let sig = if let Some(offset) = name.strip_prefix("RTMIN") {
+        min + parse_offset(offset, '+', signal)?
+    } else if let Some(offset) = name.strip_prefix("RTMAX") {
+        max - parse_offset(offset, '-', signal)?
+    } else {
+        match name.parse::<libc::c_int>() {
+            Ok(sig) if sig >= min => sig,
+            _ => return Ok(None),
+        }
+    };
+    if sig < min || sig > max {
+        bail!("{} is not a valid signal", signal);
+    }
+    Ok(Some(sig))
+}
+
+fn parse_offset(offset: &str, sign: char, signal: &str) -> Result<libc::c_int> {
+    if offset.is_empty() {
+        return Ok(0);
+    }
+    match offset.strip_prefix(sign).map(str::parse) {
+        Some(Ok(offset)) => Ok(offset),
+        _ => bail!("{} is not a valid signal", signal),
+    }
+}
+
+/// Sends the raw signal `sig` to `pid`.
+pub fn kill(pid: Pid, sig: libc::c_int) -> Result<()> {
+    Errno::result(unsafe { libc::kill(pid.as_raw(), sig) })?;
+    Ok(())
 }
@@ -1,11 +1,14 @@
 use std::collections::HashMap;
 use std::fs::File;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use caps::Capability;
 use serde::{Deserialize, Serialize};
 
+/// The version of the runtime spec this runtime implements.
+pub const OCI_VERSION: &str = "1.0.2-dev";
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Platform {
     #[serde(default)]
@@ -35,7 +38,7 @@ pub struct User {
     pub username: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct LinuxRlimits {
     #[serde(rename = "type")]
@@ -68,7 +71,7 @@ pub enum LinuxRlimitType {
     RlimitRttime,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Process {
     #[serde(default)]
@@ -81,7 +84,11 @@ pub struct Process {
     pub env: Vec<String>,
     #[serde(default)]
     pub cwd: String,
-    #[serde(default, deserialize_with = "deserialize_caps")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_caps",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub capabilities: Option<LinuxCapabilities>,
     #[serde(default)]
     pub rlimits: Vec<LinuxRlimits>,
@@ -98,7 +105,7 @@ pub struct LinuxCapabilityType {
     pub cap: Capability,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct LinuxCapabilities {
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub bounding: Vec<LinuxCapabilityType>,
@@ -112,6 +119,15 @@ pub struct LinuxCapabilities {
     pub ambient: Vec<LinuxCapabilityType>,
 }
 
+impl Serialize for LinuxCapabilityType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::ser::Serializer,
+    {
+        serializer.serialize_str(&self.cap.to_string())
+    }
+}
+
 impl<'de> Deserialize<'de> for LinuxCapabilityType {
     fn deserialize<D>(desirializer: D) -> Result<Self, D::Error>
     where
@@ -147,7 +163,7 @@ impl<'de> Deserialize<'de> for LinuxCapabilityType {
                     "CAP_SYS_NICE" => Capability::CAP_SYS_NICE,
                     "CAP_SYS_RESOURCE" => Capability::CAP_SYS_RESOURCE,
                     "CAP_SYS_TIME" => Capability::CAP_SYS_TIME,
-                    "CAP_SYS_TTYCONFIG" => Capability::CAP_SYS_TTY_CONFIG,
+                    "CAP_SYS_TTYCONFIG" | "CAP_SYS_TTY_CONFIG" => Capability::CAP_SYS_TTY_CONFIG,
                     "CAP_SYSLOG" => Capability::CAP_SYSLOG,
                     "CAP_MKNOD" => Capability::CAP_MKNOD,
                     "CAP_LEASE" => Capability::CAP_LEASE,
@@ -301,30 +317,42 @@ pub struct LinuxDeviceCgroup {
     pub allow: bool,
     #[serde(default, rename = "type")]
     pub typ: LinuxDeviceType,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub major: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub minor: Option<i64>,
     #[serde(default)]
     pub access: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Default, Serialize, Deserialize, Debug)]
 pub struct LinuxMemory {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub limit: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub reservation: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub swap: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub kernel: Option<i64>,
-    #[serde(rename = "kernelTCP")]
+    #[serde(rename = "kernelTCP", skip_serializing_if = "Option::is_none")]
     pub kernel_tcp: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub swappiness: Option<u64>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Default, Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct LinuxCPU {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub shares: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub quota: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub period: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub realtime_runtime: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub realtime_period: Option<u64>,
     #[serde(default)]
     pub cpus: String,
@@ -332,7 +360,7 @@ pub struct LinuxCPU {
     pub mems: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Default, Serialize, Deserialize, Debug)]
 pub struct LinuxPids {
     #[serde(default)]
     pub limit: i64,
@@ -345,7 +373,9 @@ pub struct LinuxWeightDevice {
     pub major: i64,
     #[serde(default)]
     pub minor: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub weight: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub leaf_weight: Option<u16>,
 }
 
@@ -359,10 +389,12 @@ pub struct LinuxThrottleDevice {
     pub rate: u64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Default, Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct LinuxBlockIO {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub blkio_weight: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub blkio_leaf_weight: Option<u16>,
     #[serde(default)]
     pub blkio_weight_device: Vec<LinuxWeightDevice>,
@@ -396,7 +428,7 @@ pub struct LinuxInterfacePriority {
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct LinuxNetwork {
-    #[serde(rename = "classID")]
+    #[serde(rename = "classID", skip_serializing_if = "Option::is_none")]
     pub class_id: Option<u32>,
     #[serde(default)]
     pub priorities: Vec<LinuxInterfacePriority>,
@@ -409,14 +441,19 @@ pub struct LinuxResources {
     pub devices: Vec<LinuxDeviceCgroup>,
     #[serde(default)]
     pub disable_oom_killer: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub oom_score_adj: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub memory: Option<LinuxMemory>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub cpu: Option<LinuxCPU>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub pids: Option<LinuxPids>,
-    #[serde(rename = "blockIO")]
+    #[serde(rename = "blockIO", skip_serializing_if = "Option::is_none")]
     pub block_io: Option<LinuxBlockIO>,
     #[serde(default)]
     pub hugepage_limits: Vec<LinuxHugepageLimit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub network: Option<LinuxNetwork>,
 }
 
@@ -451,8 +488,11 @@ pub struct LinuxDevice {
     pub major: u64,
     #[serde(default)]
     pub minor: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub file_mode: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub uid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub gid: Option<u32>,
 }
 
@@ -512,6 +552,7 @@ pub struct Linux {
     pub gid_mappings: Vec<LinuxIDMapping>,
     #[serde(default)]
     pub sysctl: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub resources: Option<LinuxResources>,
     #[serde(default)]
     pub cgroups_path: String,
@@ -529,10 +570,39 @@ pub struct Linux {
     pub mount_label: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Hook {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Hooks {
+    #[serde(default)]
+    pub prestart: Vec<Hook>,
+    #[serde(default)]
+    pub create_runtime: Vec<Hook>,
+    #[serde(default)]
+    pub create_container: Vec<Hook>,
+    #[serde(default)]
+    pub start_container: Vec<Hook>,
+    #[serde(default)]
+    pub poststart: Vec<Hook>,
+    #[serde(default)]
+    pub poststop: Vec<Hook>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Spec {
     #[serde(default, rename = "ociVersion")]
     pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub platform: Option<Platform>,
     pub process: Process,
     pub root: Root,
@@ -540,16 +610,222 @@ pub struct Spec {
     pub hostname: String,
     #[serde(default)]
     pub mounts: Vec<Mount>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hooks: Option<Hooks>,
     #[serde(default)]
     pub annotations: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub linux: Option<Linux>,
 }
 
+impl Default for Spec {
+    /// The same default configuration as `runc spec` generates.
+    fn default() -> Self {
+        let caps: Vec<LinuxCapabilityType> = vec![
+            Capability::CAP_AUDIT_WRITE,
+            Capability::CAP_KILL,
+            Capability::CAP_NET_BIND_SERVICE,
+        ]
+        .into_iter()
+        .map(|cap| LinuxCapabilityType { cap })
+        .collect();
+
+        let mount = |destination: &str, typ: &str, source: &str, options: &[&str]| Mount {
+            destination: PathBuf::from(destination),
+            typ: typ.to_string(),
+            source: PathBuf::from(source),
+            options: options.iter().map(|o| o.to_string()).collect(),
+        };
+        let namespace = |typ: LinuxNamespaceType| LinuxNamespace {
+            typ,
+            path: String::new(),
+        };
+        let to_strings = |paths: &[&str]| paths.iter().map(|p| p.to_string()).collect();
+
+        Spec {
+            version: OCI_VERSION.to_string(),
+            platform: None,
+            process: Process {
+                terminal: true,
+                console_size: Box::default(),
+                user: User {
+                    uid: 0,
+                    gid: 0,
+                    additional_gids: Vec::new(),
+                    username: String::new(),
+                },
+                args: vec!["sh".to_string()],
+                env: to_strings(&[
+                    "PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin",
+                    "TERM=xterm",
+                ]),
+                cwd: "/".to_string(),
+                capabilities: Some(LinuxCapabilities {
+                    bounding: caps.clone(),
+                    effective: caps.clone(),
+                    inheritable: Vec::new(),
+                    permitted: caps,
+                    ambient: Vec::new(),
+                }),
+                rlimits: vec![LinuxRlimits {
+                    typ: LinuxRlimitType::RlimitNofile,
+                    soft: 1024,
+                    hard: 1024,
+                }],
+                no_new_privileges: true,
+                apparmor_profile: String::new(),
+                selinux_label: String::new(),
+            },
+            root: Root {
+                path: PathBuf::from("rootfs"),
+                readonly: true,
+            },
+            hostname: "youki".to_string(),
+            mounts: vec![
+                mount("/proc", "proc", "proc", &[]),
+                mount(
+                    "/dev",
+                    "tmpfs",
+                    "tmpfs",
+                    &["nosuid", "strictatime", "mode=755", "size=65536k"],
+                ),
+                mount(
+                    "/dev/pts",
+                    "devpts",
+                    "devpts",
+                    &["nosuid", "noexec", "newinstance", "ptmxmode=0666", "mode=0620", "gid=5"],
+                ),
+                mount(
+                    "/dev/shm",
+                    "tmpfs",
+                    "shm",
+                    &["nosuid", "noexec", "nodev", "mode=1777", "size=65536k"],
+                ),
+                mount("/dev/mqueue", "mqueue", "mqueue", &["nosuid", "noexec", "nodev"]),
+                mount("/sys", "sysfs", "sysfs", &["nosuid", "noexec", "nodev", "ro"]),
+                mount(
+                    "/sys/fs/cgroup",
+                    "cgroup",
+                    "cgroup",
+                    &["nosuid", "noexec", "nodev", "relatime", "ro"],
+                ),
+            ],
+            hooks: None,
+            annotations: HashMap::new(),
+            linux: Some(Linux {
+                uid_mappings: Vec::new(),
+                gid_mappings: Vec::new(),
+                sysctl: HashMap::new(),
+                resources: Some(LinuxResources {
+                    devices: vec![LinuxDeviceCgroup {
+                        allow: false,
+                        typ: LinuxDeviceType::A,
+                        major: None,
+                        minor: None,
+                        access: "rwm".to_string(),
+                    }],
+                    ..Default::default()
+                }),
+                cgroups_path: String::new(),
+                namespaces: vec![
+                    namespace(LinuxNamespaceType::Pid),
+                    namespace(LinuxNamespaceType::Network),
+                    namespace(LinuxNamespaceType::Ipc),
+                    namespace(LinuxNamespaceType::Uts),
+                    namespace(LinuxNamespaceType::Mount),
+                ],
+                devices: Vec::new(),
+                rootfs_propagation: String::new(),
+                masked_paths: to_strings(&[
+                    "/proc/acpi",
+                    "/proc/asound",
+                    "/proc/kcore",
+                    "/proc/keys",
+                    "/proc/latency_stats",
+                    "/proc/timer_list",
+                    "/proc/timer_stats",
+                    "/proc/sched_debug",
+                    "/sys/firmware",
+                    "/proc/scsi",
+                ]),
+                readonly_paths: to_strings(&[
+                    "/proc/bus",
+                    "/proc/fs",
+                    "/proc/irq",
+                    "/proc/sys",
+                    "/proc/sysrq-trigger",
+                ]),
+                mount_label: String::new(),
+            }),
+        }
+    }
+}
+
 impl Spec {
-    pub fn load(path: &str) -> Result<Self> {
+    /// Turns the spec into one an unprivileged user can run, with root in the
+    /// container mapped to `uid` and `gid` on the host.
+    pub fn set_rootless(&mut self, uid: u32, gid: u32) {
+        let linux = self.linux.get_or_insert_with(|| Linux {
+            uid_mappings: Vec::new(),
+            gid_mappings: Vec::new(),
+            sysctl: HashMap::new(),
+            resources: None,
+            cgroups_path: String::new(),
+            namespaces: Vec::new(),
+            devices: Vec::new(),
+            rootfs_propagation: String::new(),
+            masked_paths: Vec::new(),
+            readonly_paths: Vec::new(),
+            mount_label: String::new(),
+        });
+
+        // A network namespace would be left without any interface.
+        linux.namespaces.retain(|ns| {
+            !matches!(ns.typ, LinuxNamespaceType::Network | LinuxNamespaceType::User)
+        });
+        linux.namespaces.push(LinuxNamespace {
+            typ: LinuxNamespaceType::User,
+            path: String::new(),
+        });
+        linux.uid_mappings = vec![LinuxIDMapping {
+            host_id: uid,
+            container_id: 0,
+            size: 1,
+        }];
+        linux.gid_mappings = vec![LinuxIDMapping {
+            host_id: gid,
+            container_id: 0,
+            size: 1,
+        }];
+        // Cgroups can't be configured without privileges.
+        linux.resources = None;
+
+        self.mounts.retain(|m| m.typ != "cgroup");
+        for m in &mut self.mounts {
+            if m.destination == Path::new("/sys") {
+                // sysfs can only be mounted by the owner of the network
+                // namespace, so the host's one is bound instead.
+                m.typ = "none".to_string();
+                m.source = PathBuf::from("/sys");
+                m.options = ["rbind", "nosuid", "noexec", "nodev", "ro"]
+                    .iter()
+                    .map(|o| o.to_string())
+                    .collect();
+            } else {
+                // Only the single mapped id exists inside the container.
+                m.options
+                    .retain(|o| !o.starts_with("uid=") && !o.starts_with("gid="));
+            }
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
         let file = File::open(path)?;
         let mut spec: Spec = serde_json::from_reader(&file)?;
-        spec.root.path = std::fs::canonicalize(spec.root.path)?;
+        // a relative root is relative to the bundle, not to the cwd
+        let bundle = path.parent().unwrap_or_else(|| Path::new(""));
+        spec.root.path = std::fs::canonicalize(bundle.join(&spec.root.path))?;
         Ok(spec)
     }
 }
@@ -1,6 +1,7 @@
 use std::path::PathBuf;
 
 use anyhow::{Result, bail};
+use chrono::Utc;
 use clap::Parser;
 use nix::unistd;
 
@@ -16,7 +17,7 @@ impl Start {
         log::debug!("{} is starting.", self.container_id);
         let container_root = root_path.join(&self.container_id);
         if !container_root.exists() {
-            bail!("{} doesn't exists.", self.container_id)
+            bail!("container {} does not exist", self.container_id)
         }
         let mut container = Container::load(container_root)?;
         container.refresh_status()?;
@@ -32,11 +33,19 @@ impl Start {
 
         unistd::chdir(container.root.as_os_str())?;
 
-        let mut notify_socket = NotifySocket::new(&container.root)?;
-        notify_socket.notify_container_start()?;
+        // Running is recorded before the program is started, as it may exit
+        // and be recorded as stopped before we could get to it.
+        container
+            .set_status(ContainerStatus::Running)
+            .set_started_at(Utc::now())
+            .save()?;
 
-        container.set_status(ContainerStatus::Running);
-        container.save()?;
+        let mut notify_socket = NotifySocket::new(&container.root)?;
+        if let Err(e) = notify_socket.notify_container_start() {
+            container.state.started_at = None;
+            container.set_status(ContainerStatus::Created).save()?;
+            return Err(e);
+        }
 
         log::debug!("{} started.", self.container_id);
         Ok(())
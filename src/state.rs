@@ -1,23 +1,99 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::fs;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
 use clap::Parser;
+use nix::unistd::{Uid, User};
+use serde::Serialize;
 
-use crate::container::Container;
+use crate::container::{Container, ContainerStatus};
 
 #[derive(Debug, Parser)]
 pub struct State {
     pub container_id: String,
 }
 
+/// The state of the container as runc prints it, which callers such as
+/// containerd parse, followed by the fields only this runtime records. The
+/// uid of the owner gets a key of its own, as runc's owner is a user name.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct StateOutput<'a> {
+    oci_version: &'a str,
+    id: &'a str,
+    pid: Option<i32>,
+    status: ContainerStatus,
+    bundle: &'a str,
+    rootfs: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created: Option<DateTime<Utc>>,
+    owner: String,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    annotations: &'a HashMap<String, String>,
+    schema_version: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    owner_uid: Option<u32>,
+    rootless: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cgroup_path: Option<&'a PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    systemd_unit: Option<&'a str>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    namespace_paths: &'a HashMap<String, PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    started_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    finished_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_signal: Option<i32>,
+}
+
 impl State {
     pub fn exec(&self, root_path: PathBuf) -> Result<()> {
         let root_path = fs::canonicalize(root_path)?;
         let container_root = root_path.join(&self.container_id);
+        if !container_root.exists() {
+            bail!("container {} does not exist", self.container_id)
+        }
         let mut container = Container::load(container_root)?;
         container.refresh_status()?;
-        println!("{}", serde_json::to_string_pretty(&container.state)?);
+        let owner = match container.owner() {
+            Some(uid) => match User::from_uid(Uid::from_raw(uid))? {
+                Some(user) => user.name,
+                None => format!("#{}", uid),
+            },
+            None => String::new(),
+        };
+        let state = &container.state;
+        let output = StateOutput {
+            oci_version: &state.oci_version,
+            id: &state.id,
+            pid: state.pid,
+            status: state.status,
+            bundle: &state.bundle,
+            rootfs: container
+                .rootfs()
+                .map(|rootfs| rootfs.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            created: state.created_at,
+            owner,
+            annotations: &state.annotations,
+            schema_version: state.schema_version,
+            owner_uid: state.owner,
+            rootless: state.rootless,
+            cgroup_path: state.cgroup_path.as_ref(),
+            systemd_unit: state.systemd_unit.as_deref(),
+            namespace_paths: &state.namespace_paths,
+            started_at: state.started_at,
+            finished_at: state.finished_at,
+            exit_code: state.exit_code,
+            exit_signal: state.exit_signal,
+        };
+        println!("{}", serde_json::to_string_pretty(&output)?);
         std::process::exit(0);
     }
 }
@@ -0,0 +1,129 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use clap::Parser;
+
+use crate::cgroups::{self, stats};
+use crate::container::Container;
+use crate::utils;
+
+/// Display a snapshot of the resource usage of a container
+#[derive(Debug, Parser)]
+pub struct Stats {
+    pub container_id: String,
+    /// output format, json is laid out like the data of `events --stats`
+    #[clap(short, long, default_value = "table", value_parser = ["table", "json"])]
+    pub format: String,
+}
+
+impl Stats {
+    pub fn exec(&self, root_path: PathBuf) -> Result<()> {
+        let container_root = root_path.join(&self.container_id);
+        if !container_root.exists() {
+            bail!("container {} does not exist", self.container_id)
+        }
+        let container = Container::load(container_root)?;
+        let cgroup_path = match container.cgroup_path() {
+            Some(cgroup_path) if cgroups::exists(cgroup_path)? => cgroup_path,
+            _ => bail!("{} has no cgroup to report on", container.id()),
+        };
+
+        let stats = stats::collect(cgroup_path)?;
+        if self.format == "json" {
+            println!("{}", serde_json::to_string_pretty(&stats)?);
+        } else {
+            utils::print_table(&["RESOURCE", "VALUE"], &rows(&stats));
+        }
+        Ok(())
+    }
+}
+
+/// Lays out `stats` as one row per value, memory in bytes and CPU time in
+/// nanoseconds.
+fn rows(stats: &stats::Stats) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = |name: String, value: String| rows.push(vec![name, value]);
+
+    let memory = &stats.memory;
+    row("memory.usage".into(), memory.usage.usage.to_string());
+    row("memory.peak".into(), memory.usage.max.to_string());
+    row("memory.limit".into(), limit(memory.usage.limit));
+    row("memory.cache".into(), memory.cache.to_string());
+    row("memory.swap".into(), memory.swap.usage.to_string());
+    row("memory.swap.limit".into(), limit(memory.swap.limit));
+    row("memory.failcnt".into(), memory.usage.failcnt.to_string());
+    row("memory.oom".into(), memory.oom.to_string());
+    row("memory.oom_kill".into(), memory.oom_kill.to_string());
+
+    let cpu = &stats.cpu;
+    row("cpu.usage".into(), cpu.usage.total.to_string());
+    row("cpu.user".into(), cpu.usage.user.to_string());
+    row("cpu.system".into(), cpu.usage.kernel.to_string());
+    row("cpu.periods".into(), cpu.throttling.periods.to_string());
+    row(
+        "cpu.throttled_periods".into(),
+        cpu.throttling.throttled_periods.to_string(),
+    );
+    row(
+        "cpu.throttled_time".into(),
+        cpu.throttling.throttled_time.to_string(),
+    );
+
+    row("pids.current".into(), stats.pids.current.to_string());
+    row("pids.limit".into(), limit(stats.pids.limit));
+
+    let blkio = &stats.blkio;
+    let entries = [
+        ("bytes", &blkio.io_service_bytes_recursive),
+        ("ios", &blkio.io_serviced_recursive),
+    ];
+    for (unit, entries) in entries.iter() {
+        for entry in entries.iter() {
+            let name = format!("io.{}:{}.{}_{}", entry.major, entry.minor, entry.op, unit);
+            row(name.to_lowercase(), entry.value.to_string());
+        }
+    }
+
+    let mut sizes: Vec<&String> = stats.hugetlb.keys().collect();
+    sizes.sort();
+    for size in sizes {
+        let hugetlb = &stats.hugetlb[size];
+        row(format!("hugetlb.{}.usage", size), hugetlb.usage.to_string());
+        row(format!("hugetlb.{}.peak", size), hugetlb.max.to_string());
+        row(
+            format!("hugetlb.{}.failcnt", size),
+            hugetlb.failcnt.to_string(),
+        );
+    }
+
+    let pressures = [
+        ("cpu", &cpu.psi),
+        ("memory", &memory.psi),
+        ("io", &blkio.psi),
+    ];
+    for (resource, psi) in pressures.iter() {
+        if let Some(psi) = psi {
+            for (kind, data) in [("some", &psi.some), ("full", &psi.full)].iter() {
+                row(
+                    format!("{}.pressure.{}", resource, kind),
+                    format!(
+                        "avg10={:.2} avg60={:.2} avg300={:.2} total={}",
+                        data.avg10, data.avg60, data.avg300, data.total
+                    ),
+                );
+            }
+        }
+    }
+
+    rows
+}
+
+fn limit(value: u64) -> String {
+    // no limit reads as max in v2, and as the largest page-aligned value
+    // in v1
+    if value >= i64::MAX as u64 / 4096 * 4096 {
+        "max".to_string()
+    } else {
+        value.to_string()
+    }
+}
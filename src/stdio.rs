@@ -1,11 +1,22 @@
+use std::env;
+use std::fs;
 use std::os::{unix::io::RawFd, fd::AsRawFd};
 
-use anyhow::Result;
-use nix::unistd::dup2;
+use anyhow::{bail, Result};
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+use nix::unistd::{self, dup2};
 
 const STDIN: i32 = 0;
 const STDOUT: i32 = 1;
 const STDERR: i32 = 2;
+/// The first descriptor after the standard streams, where systemd's socket
+/// activation and --preserve-fds start.
+const FIRST_EXTRA_FD: RawFd = 3;
+
+const LISTEN_FDS: &str = "LISTEN_FDS";
+const LISTEN_PID: &str = "LISTEN_PID";
+const LISTEN_FDNAMES: &str = "LISTEN_FDNAMES";
 
 #[derive(Debug)]
 pub struct FileDescriptor(RawFd);
@@ -41,3 +52,62 @@ pub fn connect_stdio(
     dup2(stderr.as_raw_fd(), STDERR)?;
     Ok(())
 }
+
+/// Returns the number of sockets systemd passed to the runtime for socket
+/// activation. Like sd_listen_fds(3), they are only ours if LISTEN_PID is
+/// our pid; otherwise the variables are removed, so that they don't reach
+/// the container's process pointing at a pid and descriptors it doesn't
+/// have.
+pub fn listen_fds() -> usize {
+    let pid = env::var(LISTEN_PID).ok().and_then(|pid| pid.parse::<i32>().ok());
+    if pid != Some(unistd::getpid().as_raw()) {
+        clear_listen_env();
+        return 0;
+    }
+    env::var(LISTEN_FDS)
+        .ok()
+        .and_then(|fds| fds.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Hands the `count` activation sockets on to the program about to be
+/// executed, which checks LISTEN_PID against its own pid.
+pub fn set_listen_env(count: usize) {
+    env::set_var(LISTEN_FDS, count.to_string());
+    env::set_var(LISTEN_PID, unistd::getpid().to_string());
+}
+
+/// Removes the variables of socket activation from our environment, which
+/// the container's process inherits.
+pub fn clear_listen_env() {
+    env::remove_var(LISTEN_FDS);
+    env::remove_var(LISTEN_PID);
+    env::remove_var(LISTEN_FDNAMES);
+}
+
+/// Keeps the `count` descriptors after the standard streams open across
+/// exec and marks every other one close-on-exec, so that nothing the
+/// runtime opened leaks into the container's process.
+pub fn close_fds_on_exec(count: usize) -> Result<()> {
+    let last_kept = FIRST_EXTRA_FD + count as RawFd;
+    for fd in FIRST_EXTRA_FD..last_kept {
+        if let Err(e) = fcntl(fd, FcntlArg::F_SETFD(FdFlag::empty())) {
+            bail!("fd {} was to be passed to the container but is not open: {}", fd, e);
+        }
+    }
+
+    // collected first, as reading the directory takes a descriptor too
+    let fds: Vec<RawFd> = fs::read_dir("/proc/self/fd")?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .collect();
+    for fd in fds.into_iter().filter(|fd| *fd >= last_kept) {
+        match fcntl(fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC)) {
+            // the descriptor of the directory, which is closed by now
+            Err(nix::Error::Sys(Errno::EBADF)) => {}
+            result => {
+                result?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,37 +1,107 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, Result};
 use clap::Parser;
-use nix::sys::signal::Signal;
 
+use crate::cgroups;
 use crate::container::{Container, ContainerStatus};
+use crate::kill;
+use crate::signal;
+use crate::spec::Spec;
 
+/// The annotation of the image holding the signal that stops it gracefully.
+const STOP_SIGNAL_ANNOTATION: &str = "org.opencontainers.image.stopSignal";
+const DEFAULT_STOP_SIGNAL: &str = "SIGTERM";
+/// How long to wait for the processes to exit after SIGKILL.
+const KILL_TIMEOUT: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Stop a container, killing it if it doesn't exit in time
 #[derive(Debug, Parser)]
 pub struct Stop {
     pub container_id: String,
+    /// signal asking the container to exit, defaults to the stopSignal
+    /// annotation or SIGTERM
+    #[clap(short, long)]
+    pub signal: Option<String>,
+    /// seconds to wait for the container to exit before killing it
+    #[clap(short, long, default_value = "10")]
+    pub timeout: u64,
 }
 
 impl Stop {
     pub fn exec(&self, root_path: PathBuf) -> Result<()> {
         let root_path = fs::canonicalize(root_path)?;
-            let container_root = root_path.join(&self.container_id);
-            if !container_root.exists() {
-                bail!("{} doesn't exists.", self.container_id)
+        let container_root = root_path.join(&self.container_id);
+        if !container_root.exists() {
+            bail!("container {} does not exist", self.container_id)
+        }
+        let mut container = Container::load(container_root.clone())?;
+        container.refresh_status()?;
+        if !container.can_kill() {
+            bail!(
+                "{} counld not be stopped because it was {:?}",
+                container.id(),
+                container.status()
+            )
+        }
+
+        let sig = signal::from_str(&self.stop_signal(&container))?;
+        log::debug!("{} is stopped with signal {}.", self.container_id, sig);
+        signal::kill(container.pid().unwrap(), sig)?;
+        // A frozen process can't act on the signal until it is thawed. The
+        // status is left to the exit of the init, as below.
+        if container.status().can_resume() {
+            if let Some(cgroup_path) = container.cgroup_path() {
+                cgroups::thaw(cgroup_path)?;
             }
-            let mut container = Container::load(container_root)?;
-            container.refresh_status()?;
-            if container.can_kill() {
-                container.do_kill(Signal::SIGKILL)?;
-                container.set_status(ContainerStatus::Stopped);
-                container.save()?;
-                std::process::exit(0)
-            } else {
-                bail!(
-                    "{} counld not be stopped because it was {:?}",
-                    container.id(),
-                    container.status()
-                )
+        }
+
+        // The state isn't saved here, as it is the exit of the init that
+        // records the container as stopped, with its exit code.
+        if !wait_for_exit(&container_root, Duration::from_secs(self.timeout))? {
+            log::debug!("{} did not exit in time and is killed.", self.container_id);
+            if let Err(e) = kill::kill_all(&container, libc::SIGKILL) {
+                log::warn!("{}; killing only its init", e);
+                signal::kill(container.pid().unwrap(), libc::SIGKILL)?;
             }
+            if !wait_for_exit(&container_root, KILL_TIMEOUT)? {
+                bail!("{} did not exit after SIGKILL", self.container_id);
+            }
+        }
+
+        log::debug!("{} was stopped.", self.container_id);
+        Ok(())
+    }
+
+    fn stop_signal(&self, container: &Container) -> String {
+        if let Some(signal) = &self.signal {
+            return signal.clone();
+        }
+        let config = Path::new(&container.state.bundle).join("config.json");
+        Spec::load(config)
+            .ok()
+            .and_then(|spec| spec.annotations.get(STOP_SIGNAL_ANNOTATION).cloned())
+            .unwrap_or_else(|| DEFAULT_STOP_SIGNAL.to_string())
+    }
+}
+
+/// Waits for the init of the container to exit, returning false if it
+/// doesn't within `timeout`.
+fn wait_for_exit(container_root: &Path, timeout: Duration) -> Result<bool> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let mut container = Container::load(container_root.to_path_buf())?;
+        container.refresh_status()?;
+        if let ContainerStatus::Stopped = container.status() {
+            return Ok(true);
+        }
+        if Instant::now() >= deadline {
+            return Ok(false);
+        }
+        thread::sleep(POLL_INTERVAL);
     }
 }
@@ -1,6 +1,6 @@
 use std::os::unix::fs::symlink;
-use std::os::unix::io::AsRawFd;
-use std::path::PathBuf;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
 
 use anyhow::{Result, bail};
 use nix::errno::Errno;
@@ -13,6 +13,21 @@ use crate::stdio;
 use crate::stdio::FileDescriptor;
 
 pub fn ready(console_fd: FileDescriptor) -> Result<()> {
+    let slave = send_console(&console_fd)?;
+
+    setsid()?;
+    if unsafe { libc::ioctl(slave, libc::TIOCSCTTY) } < 0 {
+        log::warn!("could not TIOCSCTTY")
+    };
+    let slave = FileDescriptor::from(slave);
+    stdio::connect_stdio(&slave, &slave, &slave).expect("could not dup tty to stderr");
+    close(console_fd.as_raw_fd())?;
+    Ok(())
+}
+
+/// Opens a new pseudo-terminal, sends its master to the console socket and
+/// returns its slave.
+pub fn send_console(console_fd: &FileDescriptor) -> Result<RawFd> {
     let openpty_result = nix::pty::openpty(None, None)?;
     let data: &[u8] = b"/dev/ptmx";
     let iov = [nix::sys::uio::IoVec::from_slice(data)];
@@ -25,15 +40,7 @@ pub fn ready(console_fd: FileDescriptor) -> Result<()> {
         socket::MsgFlags::empty(),
         None,
     )?;
-
-    setsid()?;
-    if unsafe { libc::ioctl(openpty_result.slave, libc::TIOCSCTTY) } < 0 {
-        log::warn!("could not TIOCSCTTY")
-    };
-    let slave = FileDescriptor::from(openpty_result.slave);
-    stdio::connect_stdio(&slave, &slave, &slave).expect("could not dup tty to stderr");
-    close(console_fd.as_raw_fd())?;
-    Ok(())
+    Ok(openpty_result.slave)
 }
 
 pub fn load_console_sockets(
@@ -77,3 +84,22 @@ pub fn load_console_sockets(
     };
     Ok((csocketfd.into(), consolefd.into()))
 }
+
+/// Connects to `console_socket` directly, for callers that do not run inside
+/// the container directory like `exec`.
+pub fn connect_console_socket(console_socket: &Path) -> Result<FileDescriptor> {
+    let csocketfd = socket::socket(
+        socket::AddressFamily::Unix,
+        socket::SockType::Stream,
+        socket::SockFlag::empty(),
+        None,
+    )?;
+    if let Err(e) = socket::connect(
+        csocketfd,
+        &socket::SockAddr::Unix(socket::UnixAddr::new(console_socket)?),
+    ) {
+        close(csocketfd)?;
+        bail!("failed to connect to {:?}: {}", console_socket, e);
+    }
+    Ok(csocketfd.into())
+}
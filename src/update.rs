@@ -0,0 +1,139 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use clap::Parser;
+
+use crate::cgroups::resources;
+use crate::cgroups::systemd;
+use crate::container::{Container, ContainerStatus};
+use crate::spec::{LinuxPids, LinuxResources};
+
+/// Update the resource limits of a container
+#[derive(Debug, Parser)]
+pub struct Update {
+    pub container_id: String,
+    /// path to an OCI LinuxResources JSON document, or `-` to read it from stdin
+    #[clap(short, long)]
+    pub resources: Option<PathBuf>,
+    /// memory limit in bytes, -1 for unlimited
+    #[clap(long, allow_hyphen_values = true)]
+    pub memory: Option<i64>,
+    /// memory soft limit in bytes
+    #[clap(long, allow_hyphen_values = true)]
+    pub memory_reservation: Option<i64>,
+    /// memory plus swap limit in bytes, -1 for unlimited swap
+    #[clap(long, allow_hyphen_values = true)]
+    pub memory_swap: Option<i64>,
+    /// relative CPU weight against other containers
+    #[clap(long)]
+    pub cpu_shares: Option<u64>,
+    /// CPU time in microseconds the container may use in each period, -1 for unlimited
+    #[clap(long, allow_hyphen_values = true)]
+    pub cpu_quota: Option<i64>,
+    /// length of a CPU period in microseconds
+    #[clap(long)]
+    pub cpu_period: Option<u64>,
+    /// CPUs the container may run on, e.g. 0-3 or 0,2
+    #[clap(long)]
+    pub cpuset_cpus: Option<String>,
+    /// memory nodes the container may use
+    #[clap(long)]
+    pub cpuset_mems: Option<String>,
+    /// maximum number of processes, 0 or less for unlimited
+    #[clap(long, allow_hyphen_values = true)]
+    pub pids_limit: Option<i64>,
+    /// block IO weight, between 10 and 1000
+    #[clap(long)]
+    pub blkio_weight: Option<u16>,
+}
+
+impl Update {
+    pub fn exec(&self, root_path: PathBuf) -> Result<()> {
+        let container_root = root_path.join(&self.container_id);
+        if !container_root.exists() {
+            bail!("container {} does not exist", self.container_id)
+        }
+        let mut container = Container::load(container_root)?;
+        container.refresh_status()?;
+        if let ContainerStatus::Stopped = container.status() {
+            bail!(
+                "{} could not be updated because it was stopped",
+                container.id()
+            )
+        }
+        let cgroup_path = match container.cgroup_path() {
+            Some(cgroup_path) => cgroup_path.to_path_buf(),
+            None => bail!("{} has no cgroup", container.id()),
+        };
+
+        let resources = self.load_resources()?;
+        match container.systemd_unit() {
+            Some(unit) => systemd::set_unit_properties(unit, &resources)?,
+            None => resources::apply(&cgroup_path, &resources)?,
+        }
+
+        log::debug!("{} was updated.", self.container_id);
+        Ok(())
+    }
+
+    /// Merges the flags into the document given with --resources, the flags
+    /// taking precedence.
+    fn load_resources(&self) -> Result<LinuxResources> {
+        let mut resources: LinuxResources = match &self.resources {
+            Some(path) if path.as_os_str() == "-" => serde_json::from_reader(io::stdin())?,
+            Some(path) => serde_json::from_reader(fs::File::open(path)?)?,
+            None => LinuxResources::default(),
+        };
+
+        if self.memory.is_some() || self.memory_reservation.is_some() || self.memory_swap.is_some()
+        {
+            let memory = resources.memory.get_or_insert_with(Default::default);
+            if self.memory.is_some() {
+                memory.limit = self.memory;
+            }
+            if self.memory_reservation.is_some() {
+                memory.reservation = self.memory_reservation;
+            }
+            if self.memory_swap.is_some() {
+                memory.swap = self.memory_swap;
+            }
+        }
+
+        if self.cpu_shares.is_some()
+            || self.cpu_quota.is_some()
+            || self.cpu_period.is_some()
+            || self.cpuset_cpus.is_some()
+            || self.cpuset_mems.is_some()
+        {
+            let cpu = resources.cpu.get_or_insert_with(Default::default);
+            if self.cpu_shares.is_some() {
+                cpu.shares = self.cpu_shares;
+            }
+            if self.cpu_quota.is_some() {
+                cpu.quota = self.cpu_quota;
+            }
+            if self.cpu_period.is_some() {
+                cpu.period = self.cpu_period;
+            }
+            if let Some(cpus) = &self.cpuset_cpus {
+                cpu.cpus = cpus.clone();
+            }
+            if let Some(mems) = &self.cpuset_mems {
+                cpu.mems = mems.clone();
+            }
+        }
+
+        if let Some(limit) = self.pids_limit {
+            resources.pids = Some(LinuxPids { limit });
+        }
+
+        if self.blkio_weight.is_some() {
+            let block_io = resources.block_io.get_or_insert_with(Default::default);
+            block_io.blkio_weight = self.blkio_weight;
+        }
+
+        Ok(resources)
+    }
+}
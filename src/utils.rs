@@ -1,11 +1,15 @@
 use std::env;
 use std::ffi::CString;
+use std::fs;
+use std::path::Path;
 
 use anyhow::{bail, Result};
 use nix::errno::Errno;
 use nix::unistd;
+use nix::unistd::{Gid, Uid};
 
-use crate::spec::LinuxRlimits;
+use crate::capabilities;
+use crate::spec::{self, LinuxRlimits};
 
 pub fn do_exec(path: &str, args: &[String]) -> Result<()> {
     let p = CString::new(path.to_string()).unwrap();
@@ -20,8 +24,9 @@ pub fn do_exec(path: &str, args: &[String]) -> Result<()> {
 
 pub fn set_env_val(env: &Vec<String>) {
     for i in 0..env.len() {
-        let split_path: Vec<&str> = env[i].split('=').collect();
-        env::set_var(split_path[0], split_path[1]);
+        if let Some((key, value)) = env[i].split_once('=') {
+            env::set_var(key, value);
+        }
     }
 }
 
@@ -36,3 +41,99 @@ pub fn set_rlimits(rlimit: &LinuxRlimits) -> Result<()> {
     }
     Ok(())
 }
+
+pub fn setid(uid: Uid, gid: Gid) -> Result<()> {
+    if let Err(e) = prctl::set_keep_capabilities(true) {
+        bail!("set keep capabilities returned {}", e);
+    };
+
+    unistd::setresgid(gid, gid, gid)?;
+    unistd::setresuid(uid, uid, uid)?;
+    if uid != Uid::from_raw(0) {
+        capabilities::reset_effective()?;
+    }
+    if let Err(e) = prctl::set_keep_capabilities(false) {
+        bail!("set keep capabilities returned {}", e);
+    };
+    Ok(())
+}
+
+/// Applies the settings of `process` to the calling process. This is shared
+/// by the container's init and by processes started with `exec`, and must be
+/// called right before `do_exec`.
+pub fn set_process(process: &spec::Process) -> Result<()> {
+    if process.no_new_privileges {
+        let _ = prctl::set_no_new_privileges(true);
+    }
+
+    set_env_val(&process.env);
+    if !process.cwd.is_empty() {
+        unistd::chdir(process.cwd.as_str())?;
+    }
+
+    if !process.user.additional_gids.is_empty() {
+        let gids: Vec<Gid> = process
+            .user
+            .additional_gids
+            .iter()
+            .map(|gid| Gid::from_raw(*gid))
+            .collect();
+        unistd::setgroups(&gids)?;
+    }
+    setid(
+        Uid::from_raw(process.user.uid),
+        Gid::from_raw(process.user.gid),
+    )?;
+
+    capabilities::reset_effective()?;
+    if let Some(caps) = &process.capabilities {
+        capabilities::set_capabilities(caps)?;
+    }
+
+    for rlimit in &process.rlimits {
+        set_rlimits(rlimit)?;
+    }
+    Ok(())
+}
+
+/// Writes `pid` to the file `path` for a supervisor to pick up. The pid is
+/// written aside and renamed into place, so that the file is never seen
+/// half written.
+pub fn write_pid_file(path: &Path, pid: i32) -> Result<()> {
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name.to_string_lossy(),
+        None => bail!("{:?} is not a valid pid file", path),
+    };
+    let tmp_path = path.with_file_name(format!(".{}.tmp", file_name));
+    if let Err(e) = fs::write(&tmp_path, pid.to_string()) {
+        bail!("failed to write pid file {:?}: {}", tmp_path, e);
+    }
+    if let Err(e) = fs::rename(&tmp_path, path) {
+        let _ = fs::remove_file(&tmp_path);
+        bail!("failed to move pid file into {:?}: {}", path, e);
+    }
+    Ok(())
+}
+
+/// Prints `rows` as a table whose columns are aligned on their widest cell.
+pub fn print_table(header: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let print_row = |cells: Vec<&str>| {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("   ").trim_end());
+    };
+    print_row(header.to_vec());
+    for row in rows {
+        print_row(row.iter().map(String::as_str).collect());
+    }
+}
@@ -0,0 +1,618 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use serde_json::Value;
+
+use crate::cgroups;
+use crate::rootfs;
+use crate::rootless;
+use crate::spec::{
+    Linux, LinuxCapabilityType, LinuxDeviceType, LinuxNamespaceType, LinuxResources, Spec,
+};
+
+const CAPABILITY_SETS: &[&str] = &[
+    "bounding",
+    "effective",
+    "inheritable",
+    "permitted",
+    "ambient",
+];
+const ROOTFS_PROPAGATIONS: &[&str] = &["", "shared", "private", "slave"];
+
+/// Check a bundle's config.json against this host
+#[derive(Debug, Parser)]
+pub struct Validate {
+    #[clap(short, long, default_value = ".")]
+    pub bundle: PathBuf,
+}
+
+/// The problems found in a config, each with the JSON path of its field.
+#[derive(Default)]
+struct Report {
+    errors: Vec<String>,
+    warnings: Vec<String>,
+}
+
+impl Report {
+    fn error<P: Display, M: Display>(&mut self, path: P, message: M) {
+        self.errors.push(format!("{}: {}", path, message));
+    }
+
+    fn warn<P: Display, M: Display>(&mut self, path: P, message: M) {
+        self.warnings.push(format!("{}: {}", path, message));
+    }
+}
+
+impl Validate {
+    pub fn exec(&self) -> Result<()> {
+        let config = self.bundle.join("config.json");
+        let content = match fs::read_to_string(&config) {
+            Ok(content) => content,
+            Err(e) => bail!("failed to read {:?}: {}", config, e),
+        };
+        let value: Value = match serde_json::from_str(&content) {
+            Ok(value) => value,
+            Err(e) => bail!("{:?} is not valid JSON: {}", config, e),
+        };
+
+        // Unknown names would fail to deserialize, so they are looked for
+        // in the raw document, where their path is still known.
+        let mut report = Report::default();
+        check_capabilities(&value, &mut report);
+        check_namespace_types(&value, &mut report);
+        match serde_json::from_str::<Spec>(&content) {
+            Ok(spec) => check_spec(&spec, &self.bundle, &mut report),
+            Err(e) if report.errors.is_empty() => report.error("config.json", e),
+            Err(_) => {}
+        }
+
+        for warning in &report.warnings {
+            println!("warning: {}", warning);
+        }
+        for error in &report.errors {
+            println!("error: {}", error);
+        }
+        if !report.errors.is_empty() {
+            bail!("{:?} has {} error(s)", config, report.errors.len());
+        }
+        println!("{:?} is valid", config);
+        Ok(())
+    }
+}
+
+fn check_capabilities(value: &Value, report: &mut Report) {
+    let supported = caps::runtime::thread_all_supported();
+    for set in CAPABILITY_SETS {
+        let pointer = format!("/process/capabilities/{}", set);
+        let caps = match value.pointer(&pointer).and_then(Value::as_array) {
+            Some(caps) => caps,
+            None => continue,
+        };
+        for (i, cap) in caps.iter().enumerate() {
+            let path = format!("process.capabilities.{}[{}]", set, i);
+            match serde_json::from_value::<LinuxCapabilityType>(cap.clone()) {
+                Ok(c) if !supported.contains(&c.cap) => {
+                    report.error(path, format!("{} is not supported by this kernel", cap))
+                }
+                Ok(_) => {}
+                Err(_) => report.error(path, format!("{} is not a known capability", cap)),
+            }
+        }
+    }
+}
+
+fn check_namespace_types(value: &Value, report: &mut Report) {
+    let namespaces = match value.pointer("/linux/namespaces").and_then(Value::as_array) {
+        Some(namespaces) => namespaces,
+        None => return,
+    };
+    for (i, ns) in namespaces.iter().enumerate() {
+        let path = format!("linux.namespaces[{}].type", i);
+        let typ = ns.get("type").cloned().unwrap_or(Value::Null);
+        match serde_json::from_value::<LinuxNamespaceType>(typ.clone()) {
+            Ok(t) if !Path::new("/proc/self/ns").join(proc_name(t)).exists() => {
+                report.error(path, format!("{} is not supported by this kernel", typ))
+            }
+            Ok(_) => {}
+            Err(_) => report.error(path, format!("{} is not a known namespace type", typ)),
+        }
+    }
+}
+
+/// Returns the name of a namespace in /proc/<pid>/ns.
+fn proc_name(typ: LinuxNamespaceType) -> &'static str {
+    match typ {
+        LinuxNamespaceType::Mount => "mnt",
+        LinuxNamespaceType::Cgroup => "cgroup",
+        LinuxNamespaceType::Uts => "uts",
+        LinuxNamespaceType::Ipc => "ipc",
+        LinuxNamespaceType::User => "user",
+        LinuxNamespaceType::Pid => "pid",
+        LinuxNamespaceType::Network => "net",
+    }
+}
+
+fn check_spec(spec: &Spec, bundle: &Path, report: &mut Report) {
+    if spec.process.args.is_empty() {
+        report.error("process.args", "must not be empty");
+    }
+    if !spec.process.cwd.is_empty() && !spec.process.cwd.starts_with('/') {
+        report.error("process.cwd", "must be an absolute path");
+    }
+    if !spec.process.apparmor_profile.is_empty() {
+        report.warn("process.apparmorProfile", "is ignored");
+    }
+    if !spec.process.selinux_label.is_empty() {
+        report.warn("process.selinuxLabel", "is ignored");
+    }
+
+    let root = &spec.root.path;
+    if root.as_os_str().is_empty() {
+        report.error("root.path", "must not be empty");
+    } else if !bundle.join(root).is_dir() {
+        let relative = if root.is_relative() {
+            " relative to the bundle"
+        } else {
+            ""
+        };
+        report.error(
+            "root.path",
+            format!("{:?} is not an existing directory{}", root, relative),
+        );
+    }
+    if spec.root.readonly {
+        report.warn("root.readonly", "is ignored");
+    }
+
+    let supported_options = rootfs::supported_mount_options();
+    for (i, m) in spec.mounts.iter().enumerate() {
+        if m.destination.is_relative() {
+            report.error(
+                format!("mounts[{}].destination", i),
+                "must be an absolute path",
+            );
+        }
+        if m.typ == "cgroup" {
+            report.warn(format!("mounts[{}].type", i), "cgroup mounts are skipped");
+        }
+        let bind = m.typ == "bind" || m.options.iter().any(|o| o == "bind" || o == "rbind");
+        if bind && m.source.is_relative() {
+            report.error(format!("mounts[{}].source", i), "must be an absolute path");
+        } else if bind && !m.source.exists() {
+            report.error(
+                format!("mounts[{}].source", i),
+                format!("{:?} does not exist", m.source),
+            );
+        }
+        for (j, option) in m.options.iter().enumerate() {
+            let path = format!("mounts[{}].options[{}]", i, j);
+            if option.is_empty() {
+                report.error(path, "must not be empty");
+            } else if !option.contains('=') && !supported_options.contains(&option.as_str()) {
+                report.warn(
+                    path,
+                    format!(
+                        "{:?} is not a known flag and is passed to the filesystem as data",
+                        option
+                    ),
+                );
+            }
+        }
+    }
+
+    if let Some(hooks) = &spec.hooks {
+        let ignored = [
+            ("prestart", &hooks.prestart),
+            ("createRuntime", &hooks.create_runtime),
+            ("createContainer", &hooks.create_container),
+            ("startContainer", &hooks.start_container),
+            ("poststart", &hooks.poststart),
+        ];
+        for (name, list) in ignored.iter() {
+            if !list.is_empty() {
+                report.warn(
+                    format!("hooks.{}", name),
+                    "is ignored, only poststop hooks are run",
+                );
+            }
+        }
+        for (i, hook) in hooks.poststop.iter().enumerate() {
+            if hook.path.is_relative() {
+                report.error(
+                    format!("hooks.poststop[{}].path", i),
+                    "must be an absolute path",
+                );
+            }
+        }
+    }
+
+    match &spec.linux {
+        Some(linux) => check_linux(linux, report),
+        None => report.error("linux", "is required"),
+    }
+}
+
+fn check_linux(linux: &Linux, report: &mut Report) {
+    if !ROOTFS_PROPAGATIONS.contains(&linux.rootfs_propagation.as_str()) {
+        report.error(
+            "linux.rootfsPropagation",
+            format!(
+                "must be one of shared, private or slave, not {:?}",
+                linux.rootfs_propagation
+            ),
+        );
+    }
+
+    for (i, dev) in linux.devices.iter().enumerate() {
+        if !dev.path.starts_with("/dev/") || dev.path.contains("..") {
+            report.error(
+                format!("linux.devices[{}].path", i),
+                format!("{:?} is not a path in /dev", dev.path),
+            );
+        }
+        if let LinuxDeviceType::A = dev.typ {
+            report.error(
+                format!("linux.devices[{}].type", i),
+                "must be one of b, c, u or p",
+            );
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut new_userns = false;
+    let mut userns = false;
+    for (i, ns) in linux.namespaces.iter().enumerate() {
+        if !seen.insert(proc_name(ns.typ)) {
+            report.error(format!("linux.namespaces[{}]", i), "is a duplicate");
+        }
+        if let LinuxNamespaceType::User = ns.typ {
+            userns = true;
+            new_userns = ns.path.is_empty();
+        }
+        if !ns.path.is_empty() && !Path::new(&ns.path).exists() {
+            report.error(
+                format!("linux.namespaces[{}].path", i),
+                format!("{:?} does not exist", ns.path),
+            );
+        }
+    }
+    let mappings = [
+        ("linux.uidMappings", &linux.uid_mappings),
+        ("linux.gidMappings", &linux.gid_mappings),
+    ];
+    for (path, mapping) in mappings.iter() {
+        if new_userns && mapping.is_empty() {
+            report.error(path, "is required to create a user namespace");
+        } else if !userns && !mapping.is_empty() {
+            report.warn(path, "is ignored without a user namespace");
+        }
+        for (i, m) in mapping.iter().enumerate() {
+            if m.size == 0 {
+                report.error(format!("{}[{}].size", path, i), "must not be 0");
+            }
+        }
+    }
+    if rootless::is_rootless() && !userns {
+        report.error(
+            "linux.namespaces",
+            "rootless containers require a user namespace",
+        );
+    }
+
+    if !linux.sysctl.is_empty() {
+        report.warn("linux.sysctl", "is ignored");
+    }
+    if !linux.masked_paths.is_empty() {
+        report.warn("linux.maskedPaths", "is ignored");
+    }
+    if !linux.readonly_paths.is_empty() {
+        report.warn("linux.readonlyPaths", "is ignored");
+    }
+
+    if let Some(resources) = &linux.resources {
+        check_resources(resources, report);
+    }
+}
+
+/// The cgroup controllers the host provides.
+struct Controllers {
+    v2: bool,
+    available: HashSet<String>,
+}
+
+impl Controllers {
+    fn detect() -> Self {
+        let v2 = cgroups::is_v2();
+        let available = if v2 {
+            let file = Path::new(cgroups::DEFAULT_CGROUP_ROOT).join("cgroup.controllers");
+            fs::read_to_string(file)
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(String::from)
+                .collect()
+        } else {
+            [
+                "memory", "cpu", "cpuset", "pids", "blkio", "hugetlb", "net_cls", "net_prio",
+            ]
+            .iter()
+            .filter(|c| cgroups::v1_dir(Path::new("/"), c).is_ok())
+            .map(|c| c.to_string())
+            .collect()
+        };
+        Self { v2, available }
+    }
+
+    fn require(&self, controller: &str, path: &str, report: &mut Report) {
+        if !self.available.contains(controller) {
+            let version = if self.v2 { "v2" } else { "v1" };
+            report.error(
+                path,
+                format!(
+                    "the {} controller is not available in cgroup {}",
+                    controller, version
+                ),
+            );
+        }
+    }
+}
+
+fn check_resources(resources: &LinuxResources, report: &mut Report) {
+    let controllers = Controllers::detect();
+    let v2 = controllers.v2;
+    let ignored_in_v2 = "is ignored in cgroup v2";
+
+    if !resources.devices.is_empty() {
+        report.warn(
+            "linux.resources.devices",
+            "is ignored, the device cgroup is not implemented",
+        );
+    }
+
+    if let Some(memory) = &resources.memory {
+        controllers.require("memory", "linux.resources.memory", report);
+        if v2 {
+            if memory.kernel.is_some() {
+                report.warn("linux.resources.memory.kernel", ignored_in_v2);
+            }
+            if memory.kernel_tcp.is_some() {
+                report.warn("linux.resources.memory.kernelTCP", ignored_in_v2);
+            }
+            if memory.swappiness.is_some() {
+                report.warn("linux.resources.memory.swappiness", ignored_in_v2);
+            }
+        }
+        match (memory.swap, memory.limit) {
+            (Some(swap), Some(limit)) if swap != -1 && limit >= 0 && swap < limit => report.error(
+                "linux.resources.memory.swap",
+                format!("must not be lower than the memory limit {}", limit),
+            ),
+            (Some(swap), None) if v2 && swap != -1 => report.error(
+                "linux.resources.memory.swap",
+                "needs linux.resources.memory.limit in cgroup v2",
+            ),
+            _ => {}
+        }
+    }
+    if resources.disable_oom_killer && v2 {
+        report.warn("linux.resources.disableOOMKiller", ignored_in_v2);
+    }
+
+    if let Some(cpu) = &resources.cpu {
+        if cpu.shares.is_some() || cpu.quota.is_some() || cpu.period.is_some() {
+            controllers.require("cpu", "linux.resources.cpu", report);
+        }
+        if cpu.realtime_runtime.is_some() || cpu.realtime_period.is_some() {
+            if v2 {
+                report.warn("linux.resources.cpu.realtimeRuntime", ignored_in_v2);
+            } else {
+                controllers.require("cpu", "linux.resources.cpu", report);
+            }
+        }
+        if !cpu.cpus.is_empty() || !cpu.mems.is_empty() {
+            controllers.require("cpuset", "linux.resources.cpu", report);
+        }
+    }
+
+    if resources.pids.is_some() {
+        controllers.require("pids", "linux.resources.pids", report);
+    }
+
+    if let Some(block_io) = &resources.block_io {
+        controllers.require(
+            if v2 { "io" } else { "blkio" },
+            "linux.resources.blockIO",
+            report,
+        );
+        if let Some(weight) = block_io.blkio_weight {
+            if !(10..=1000).contains(&weight) {
+                report.error(
+                    "linux.resources.blockIO.blkioWeight",
+                    "must be between 10 and 1000",
+                );
+            }
+        }
+        let leaf_weights = block_io.blkio_leaf_weight.is_some()
+            || block_io
+                .blkio_weight_device
+                .iter()
+                .any(|d| d.leaf_weight.is_some());
+        if v2 && leaf_weights {
+            report.warn("linux.resources.blockIO.blkioLeafWeight", ignored_in_v2);
+        }
+    }
+
+    if !resources.hugepage_limits.is_empty() {
+        controllers.require("hugetlb", "linux.resources.hugepageLimits", report);
+        let sizes = cgroups::hugepage_sizes();
+        for (i, limit) in resources.hugepage_limits.iter().enumerate() {
+            if !sizes.contains(&limit.page_size) {
+                report.error(
+                    format!("linux.resources.hugepageLimits[{}].pageSize", i),
+                    format!("{:?} is not a huge page size of this host", limit.page_size),
+                );
+            }
+        }
+    }
+
+    if let Some(network) = &resources.network {
+        if v2 {
+            report.warn("linux.resources.network", ignored_in_v2);
+        } else {
+            if network.class_id.is_some() {
+                controllers.require("net_cls", "linux.resources.network.classID", report);
+            }
+            if !network.priorities.is_empty() {
+                controllers.require("net_prio", "linux.resources.network.priorities", report);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::spec::{LinuxDevice, LinuxNamespace};
+
+    /// Checks `spec` against a bundle holding a rootfs directory and returns
+    /// the JSON paths of the errors reported.
+    fn error_paths(name: &str, spec: &Spec) -> Vec<String> {
+        let bundle = std::env::temp_dir().join(format!("validate-{}-{}", name, std::process::id()));
+        fs::create_dir_all(bundle.join("rootfs")).unwrap();
+        let mut report = Report::default();
+        check_spec(spec, &bundle, &mut report);
+        fs::remove_dir_all(&bundle).unwrap();
+        report
+            .errors
+            .iter()
+            .map(|error| error.split(": ").next().unwrap().to_string())
+            .collect()
+    }
+
+    fn linux(spec: &mut Spec) -> &mut Linux {
+        spec.linux.as_mut().unwrap()
+    }
+
+    fn device(path: &str) -> LinuxDevice {
+        serde_json::from_value(json!({ "path": path, "type": "c", "major": 1, "minor": 3 }))
+            .unwrap()
+    }
+
+    #[test]
+    fn default_spec() {
+        let mut spec = Spec::default();
+        if rootless::is_rootless() {
+            spec.set_rootless(1000, 1000);
+        }
+        // only the controllers it asks for depend on the host
+        let paths = error_paths("default", &spec);
+        assert!(
+            paths.iter().all(|p| p.starts_with("linux.resources")),
+            "{:?}",
+            paths
+        );
+    }
+
+    #[test]
+    fn empty_args() {
+        let mut spec = Spec::default();
+        spec.process.args.clear();
+        assert!(error_paths("args", &spec).contains(&"process.args".to_string()));
+    }
+
+    #[test]
+    fn missing_root() {
+        let mut spec = Spec::default();
+        spec.root.path = PathBuf::from("missing");
+        assert!(error_paths("relative-root", &spec).contains(&"root.path".to_string()));
+
+        spec.root.path = PathBuf::from("/nonexistent/rootfs");
+        assert!(error_paths("absolute-root", &spec).contains(&"root.path".to_string()));
+
+        spec.root.path = PathBuf::new();
+        assert!(error_paths("empty-root", &spec).contains(&"root.path".to_string()));
+    }
+
+    #[test]
+    fn bad_device_paths() {
+        let mut spec = Spec::default();
+        linux(&mut spec).devices = vec![
+            device("/dev/null"),
+            device("/tmp/null"),
+            device("/dev/../etc/passwd"),
+        ];
+        let paths = error_paths("devices", &spec);
+        assert!(
+            !paths.contains(&"linux.devices[0].path".to_string()),
+            "{:?}",
+            paths
+        );
+        assert!(
+            paths.contains(&"linux.devices[1].path".to_string()),
+            "{:?}",
+            paths
+        );
+        assert!(
+            paths.contains(&"linux.devices[2].path".to_string()),
+            "{:?}",
+            paths
+        );
+    }
+
+    #[test]
+    fn user_namespace_without_mappings() {
+        let mut spec = Spec::default();
+        let linux = linux(&mut spec);
+        linux.namespaces.push(LinuxNamespace {
+            typ: LinuxNamespaceType::User,
+            path: String::new(),
+        });
+        linux.uid_mappings.clear();
+        linux.gid_mappings.clear();
+        let paths = error_paths("userns", &spec);
+        assert!(
+            paths.contains(&"linux.uidMappings".to_string()),
+            "{:?}",
+            paths
+        );
+        assert!(
+            paths.contains(&"linux.gidMappings".to_string()),
+            "{:?}",
+            paths
+        );
+    }
+
+    #[test]
+    fn duplicate_namespace() {
+        let mut spec = Spec::default();
+        let linux = linux(&mut spec);
+        let index = linux.namespaces.len();
+        linux.namespaces.push(LinuxNamespace {
+            typ: LinuxNamespaceType::Pid,
+            path: String::new(),
+        });
+        let paths = error_paths("duplicate-ns", &spec);
+        assert!(
+            paths.contains(&format!("linux.namespaces[{}]", index)),
+            "{:?}",
+            paths
+        );
+    }
+
+    #[test]
+    fn bad_rootfs_propagation() {
+        let mut spec = Spec::default();
+        linux(&mut spec).rootfs_propagation = "rshared-ish".to_string();
+        assert!(error_paths("propagation", &spec).contains(&"linux.rootfsPropagation".to_string()));
+
+        linux(&mut spec).rootfs_propagation = "slave".to_string();
+        assert!(
+            !error_paths("propagation-ok", &spec).contains(&"linux.rootfsPropagation".to_string())
+        );
+    }
+}
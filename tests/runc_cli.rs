@@ -0,0 +1,327 @@
+//! Drives the runtime with the command lines the runc shim of containerd
+//! issues through go-runc, in the same order, and checks the outputs it
+//! parses: the pid file, the JSON of state, ps and events, and the error
+//! messages it matches on.
+
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+const RUNTIME: &str = env!("CARGO_BIN_EXE_rust_runtime");
+
+struct Shim {
+    dir: PathBuf,
+    root: PathBuf,
+    bundle: PathBuf,
+    log: PathBuf,
+}
+
+impl Shim {
+    /// Creates a bundle running `sleep` on a rootfs borrowing /usr from the
+    /// host, generated by `spec` like `runc spec` would.
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("runc-cli-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let root = dir.join("root");
+        let bundle = dir.join("bundle");
+        let rootfs = bundle.join("rootfs");
+        fs::create_dir_all(rootfs.join("usr")).unwrap();
+        let status = Command::new(RUNTIME)
+            .args(["spec", "--bundle"])
+            .arg(&bundle)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let mut mounts = vec![json!({
+            "destination": "/usr",
+            "type": "bind",
+            "source": "/usr",
+            "options": ["rbind"],
+        })];
+        for dir in &["bin", "sbin", "lib", "lib64"] {
+            let host = Path::new("/").join(dir);
+            if let Ok(target) = fs::read_link(&host) {
+                symlink(target, rootfs.join(dir)).unwrap();
+            } else if host.is_dir() {
+                fs::create_dir(rootfs.join(dir)).unwrap();
+                mounts.push(json!({
+                    "destination": host,
+                    "type": "bind",
+                    "source": host,
+                    "options": ["rbind"],
+                }));
+            }
+        }
+
+        let config = bundle.join("config.json");
+        let mut spec: Value = serde_json::from_str(&fs::read_to_string(&config).unwrap()).unwrap();
+        spec["process"]["terminal"] = json!(false);
+        spec["process"]["args"] = json!(["sleep", "1000"]);
+        spec["root"]["readonly"] = json!(false);
+        spec["mounts"].as_array_mut().unwrap().extend(mounts);
+        fs::write(&config, spec.to_string()).unwrap();
+
+        let log = bundle.join("log.json");
+        Self {
+            dir,
+            root,
+            bundle,
+            log,
+        }
+    }
+
+    /// Runs the runtime with the global flags the shim passes.
+    fn runc(&self, args: &[&str]) -> Output {
+        self.runc_with_stdin(args, None)
+    }
+
+    fn runc_with_stdin(&self, args: &[&str], stdin: Option<&str>) -> Output {
+        // The container inherits the output of create and exec, so it goes
+        // to files, which unlike pipes don't wait for the container to exit.
+        let stdout = self.dir.join("stdout");
+        let stderr = self.dir.join("stderr");
+        let mut child = Command::new(RUNTIME)
+            .arg("--root")
+            .arg(&self.root)
+            .arg("--log")
+            .arg(&self.log)
+            .args(["--log-format", "json", "--rootless=false"])
+            .args(args)
+            .current_dir(&self.dir)
+            .stdin(Stdio::piped())
+            .stdout(fs::File::create(&stdout).unwrap())
+            .stderr(fs::File::create(&stderr).unwrap())
+            .spawn()
+            .unwrap();
+        let mut pipe = child.stdin.take().unwrap();
+        if let Some(stdin) = stdin {
+            pipe.write_all(stdin.as_bytes()).unwrap();
+        }
+        drop(pipe);
+        Output {
+            status: child.wait().unwrap(),
+            stdout: fs::read(&stdout).unwrap(),
+            stderr: fs::read(&stderr).unwrap(),
+        }
+    }
+
+    fn ok(&self, args: &[&str]) -> String {
+        let output = self.runc(args);
+        assert!(
+            output.status.success(),
+            "{:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    fn state(&self, id: &str) -> Value {
+        serde_json::from_str(&self.ok(&["state", id])).unwrap()
+    }
+
+    fn wait_for_status(&self, id: &str, status: &str) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while self.state(id)["status"] != status {
+            assert!(Instant::now() < deadline, "{} never became {}", id, status);
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    /// Returns the last error the runtime logged, which is where the shim
+    /// takes the message of a failed command from.
+    fn last_logged_error(&self) -> String {
+        let log = fs::read_to_string(&self.log).unwrap();
+        let error = log
+            .lines()
+            .rev()
+            .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+            .find(|record| record["level"] == "error")
+            .expect("no error was logged");
+        error["msg"].as_str().unwrap().to_string()
+    }
+}
+
+impl Drop for Shim {
+    fn drop(&mut self) {
+        // what a failed test left behind
+        let output = self.runc(&["list", "--quiet"]);
+        for id in String::from_utf8_lossy(&output.stdout).lines() {
+            self.runc(&["delete", "--force", id]);
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Containers need root here, as the spec has no user namespace.
+fn is_root() -> bool {
+    nix::unistd::geteuid().is_root()
+}
+
+fn pid_in(path: &Path) -> i32 {
+    fs::read_to_string(path).unwrap().trim().parse().unwrap()
+}
+
+#[test]
+fn containerd_shim_lifecycle() {
+    if !is_root() {
+        eprintln!("skipped, containers need root");
+        return;
+    }
+    let shim = Shim::new("lifecycle");
+    let id = "shim-lifecycle";
+    let bundle = shim.bundle.to_str().unwrap();
+
+    let pid_file = shim.bundle.join("init.pid");
+    shim.ok(&[
+        "create",
+        "--bundle",
+        bundle,
+        "--pid-file",
+        pid_file.to_str().unwrap(),
+        "--no-new-keyring",
+        id,
+    ]);
+    let init_pid = pid_in(&pid_file);
+    // go-runc decodes these into typed fields, so each must appear once and
+    // with the type runc gives it
+    let raw = shim.ok(&["state", id]);
+    let state: Value = serde_json::from_str(&raw).unwrap();
+    let runc_keys = [
+        "ociVersion",
+        "id",
+        "pid",
+        "status",
+        "bundle",
+        "rootfs",
+        "created",
+        "owner",
+    ];
+    for key in &runc_keys {
+        // top-level keys, as namespacePaths has a pid key of its own
+        let count = raw.matches(&format!("\n  \"{}\":", key)).count();
+        assert_eq!(count, 1, "{} is not there once", key);
+    }
+    assert_eq!(state["owner"], "root");
+    // what runc doesn't have follows as extra keys
+    assert_eq!(state["ownerUid"], 0);
+    assert!(state["namespacePaths"].is_object());
+    assert_eq!(state["status"], "created");
+    assert_eq!(state["pid"], init_pid);
+    assert_eq!(state["bundle"], bundle);
+    assert!(state["created"].is_string());
+
+    shim.ok(&["start", id]);
+    shim.wait_for_status(id, "running");
+
+    let pids: Vec<i32> = serde_json::from_str(&shim.ok(&["ps", "--format", "json", id])).unwrap();
+    assert!(pids.contains(&init_pid));
+
+    let process = shim.bundle.join("process.json");
+    fs::write(
+        &process,
+        json!({
+            "terminal": false,
+            "user": { "uid": 0, "gid": 0 },
+            "args": ["sleep", "1000"],
+            "env": ["PATH=/usr/bin:/bin"],
+            "cwd": "/",
+        })
+        .to_string(),
+    )
+    .unwrap();
+    let exec_pid_file = shim.bundle.join("exec.pid");
+    shim.ok(&[
+        "exec",
+        "--process",
+        process.to_str().unwrap(),
+        "--detach",
+        "--pid-file",
+        exec_pid_file.to_str().unwrap(),
+        id,
+    ]);
+    let exec_pid = pid_in(&exec_pid_file);
+    let pids: Vec<i32> = serde_json::from_str(&shim.ok(&["ps", "--format", "json", id])).unwrap();
+    assert!(pids.contains(&exec_pid));
+
+    let output = shim.runc_with_stdin(
+        &["update", "--resources", "-", id],
+        Some(r#"{"pids":{"limit":100}}"#),
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let event: Value = serde_json::from_str(&shim.ok(&["events", "--stats", id])).unwrap();
+    assert_eq!(event["type"], "stats");
+    assert_eq!(event["id"], id);
+    assert!(event["data"]["pids"]["current"].as_u64().unwrap() >= 2);
+
+    shim.ok(&["pause", id]);
+    shim.wait_for_status(id, "paused");
+    shim.ok(&["resume", id]);
+    shim.wait_for_status(id, "running");
+
+    shim.ok(&["kill", "--all", id, "9"]);
+    shim.wait_for_status(id, "stopped");
+
+    // containerd treats these as the process having already exited
+    let output = shim.runc(&["kill", id, "15"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(shim.last_logged_error().contains("container not running"));
+
+    let list: Value = serde_json::from_str(&shim.ok(&["list", "--format", "json"])).unwrap();
+    assert_eq!(list[0]["id"], id);
+    assert!(list[0]["rootfs"].as_str().unwrap().ends_with("rootfs"));
+
+    shim.ok(&["delete", id]);
+    assert!(!shim.root.join(id).exists());
+}
+
+#[test]
+fn containerd_shim_errors() {
+    if !is_root() {
+        eprintln!("skipped, containers need root");
+        return;
+    }
+    let shim = Shim::new("errors");
+
+    // a delete of a container that is already gone is ignored by its
+    // message
+    for args in [
+        &["delete", "missing"][..],
+        &["state", "missing"],
+        &["kill", "missing", "9"],
+    ] {
+        let output = shim.runc(args);
+        assert_eq!(output.status.code(), Some(1), "{:?}", args);
+        assert!(
+            shim.last_logged_error().contains("does not exist"),
+            "{:?}",
+            args
+        );
+        // the log already holds the error, so it is printed once more for
+        // the caller reading stderr
+        assert!(String::from_utf8_lossy(&output.stderr).contains("does not exist"));
+    }
+    shim.ok(&["delete", "--force", "missing"]);
+
+    // the global flags of runc are accepted
+    shim.ok(&[
+        "--criu",
+        "/usr/sbin/criu",
+        "--systemd-cgroup",
+        "list",
+        "--format",
+        "json",
+    ]);
+}
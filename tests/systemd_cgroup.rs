@@ -0,0 +1,248 @@
+//! Drives the systemd cgroup manager against a mock of systemd, answering on
+//! a private dbus-daemon the calls the real one would, and checks what the
+//! runtime asks of it.
+
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use nix::unistd::Pid;
+
+use rust_runtime::cgroups::{self, systemd};
+use rust_runtime::dbus::{Connection, Message, Value, METHOD_CALL};
+use rust_runtime::spec::LinuxResources;
+
+const SYSTEMD_DESTINATION: &str = "org.freedesktop.systemd1";
+const SYSTEMD_PATH: &str = "/org/freedesktop/systemd1";
+const MANAGER_INTERFACE: &str = "org.freedesktop.systemd1.Manager";
+
+const BUS_CONFIG: &str = r#"<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-Bus Bus Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig>
+  <listen>unix:path={socket}</listen>
+  <auth>EXTERNAL</auth>
+  <policy context="default">
+    <allow user="*"/>
+    <allow own="*"/>
+    <allow send_destination="*"/>
+    <allow receive_sender="*"/>
+  </policy>
+</busconfig>
+"#;
+
+/// A private bus, with a mock of systemd recording the calls made to it.
+struct Bus {
+    dir: PathBuf,
+    daemon: Child,
+    calls: Arc<Mutex<Vec<Message>>>,
+}
+
+impl Bus {
+    /// Starts the bus and the mock, and points the runtime at the bus as
+    /// its system bus. Returns None if there is no dbus-daemon to run.
+    fn start() -> Option<Self> {
+        let dir = env::temp_dir().join(format!("systemd-cgroup-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("bus.conf");
+        let socket = dir.join("bus");
+        fs::write(
+            &config,
+            BUS_CONFIG.replace("{socket}", socket.to_str().unwrap()),
+        )
+        .unwrap();
+
+        let mut daemon = match Command::new("dbus-daemon")
+            .arg("--nofork")
+            .arg("--print-address")
+            .arg(format!("--config-file={}", config.display()))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(daemon) => daemon,
+            Err(_) => {
+                let _ = fs::remove_dir_all(&dir);
+                return None;
+            }
+        };
+        // the address is printed once the bus is listening
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        let address = address.trim().to_string();
+        env::set_var("DBUS_SYSTEM_BUS_ADDRESS", &address);
+
+        let mut connection = Connection::open(&address).unwrap();
+        connection.request_name(SYSTEMD_DESTINATION).unwrap();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let recorded = calls.clone();
+        thread::spawn(move || mock_systemd(connection, recorded));
+
+        Some(Self { dir, daemon, calls })
+    }
+
+    /// Returns the calls of `member` made so far.
+    fn calls(&self, member: &str) -> Vec<Message> {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|call| call.member.as_deref() == Some(member))
+            .cloned()
+            .collect()
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Answers the calls of the manager interface until the bus goes away. Jobs
+/// complete at once, failing for units named failing-*, and units named
+/// missing-* don't exist.
+fn mock_systemd(mut connection: Connection, calls: Arc<Mutex<Vec<Message>>>) {
+    let mut jobs = 0;
+    while let Ok(call) = connection.read_message(None) {
+        if call.typ != METHOD_CALL {
+            continue;
+        }
+        calls.lock().unwrap().push(call.clone());
+        let unit = call
+            .body
+            .first()
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        let reply = match call.member.as_deref() {
+            Some("Subscribe") | Some("SetUnitProperties") => {
+                Message::method_return(&call, Vec::new())
+            }
+            Some("StopUnit") if unit.starts_with("missing-") => Message::error(
+                &call,
+                "org.freedesktop.systemd1.NoSuchUnit",
+                &format!("Unit {} not loaded.", unit),
+            ),
+            Some("StartTransientUnit") | Some("StopUnit") => {
+                jobs += 1;
+                let job = format!("{}/job/{}", SYSTEMD_PATH, jobs);
+                let reply = Message::method_return(&call, vec![Value::ObjectPath(job.clone())]);
+                connection.send(reply).unwrap();
+                let result = if unit.starts_with("failing-") {
+                    "failed"
+                } else {
+                    "done"
+                };
+                Message::signal(
+                    SYSTEMD_PATH,
+                    MANAGER_INTERFACE,
+                    "JobRemoved",
+                    vec![
+                        Value::UInt32(jobs),
+                        Value::ObjectPath(job),
+                        Value::Str(unit),
+                        Value::Str(result.to_string()),
+                    ],
+                )
+            }
+            _ => Message::error(
+                &call,
+                "org.freedesktop.DBus.Error.UnknownMethod",
+                "unknown method",
+            ),
+        };
+        connection.send(reply).unwrap();
+    }
+}
+
+/// Returns the value of the property `name` in the `a(sv)` array `properties`.
+fn property<'a>(properties: &'a Value, name: &str) -> Option<&'a Value> {
+    let properties = match properties {
+        Value::Array(_, properties) => properties,
+        _ => panic!("{:?} is not an array of properties", properties),
+    };
+    properties.iter().find_map(|property| match property {
+        Value::Struct(fields) if fields[0].as_str() == Some(name) => match &fields[1] {
+            Value::Variant(value) => Some(value.as_ref()),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+fn resources(json: &str) -> LinuxResources {
+    serde_json::from_str(json).unwrap()
+}
+
+#[test]
+fn systemd_cgroup_on_mock_bus() {
+    let bus = match Bus::start() {
+        Some(bus) => bus,
+        None => {
+            eprintln!("skipped, dbus-daemon is not installed");
+            return;
+        }
+    };
+
+    let unit = systemd::Unit::parse("machine.slice:test:c1", "c1").unwrap();
+    let limits = resources(r#"{"memory":{"limit":1073741824},"pids":{"limit":100}}"#);
+    systemd::start_unit(&unit, Pid::from_raw(4242), Some(&limits)).unwrap();
+
+    let start = &bus.calls("StartTransientUnit")[0];
+    assert_eq!(start.destination.as_deref(), Some(SYSTEMD_DESTINATION));
+    assert_eq!(start.body[0].as_str(), Some("test-c1.scope"));
+    assert_eq!(start.body[1].as_str(), Some("replace"));
+    let properties = &start.body[2];
+    assert_eq!(
+        property(properties, "Slice"),
+        Some(&Value::Str("machine.slice".to_string()))
+    );
+    assert_eq!(
+        property(properties, "PIDs"),
+        Some(&Value::Array("u".to_string(), vec![Value::UInt32(4242)]))
+    );
+    assert_eq!(property(properties, "Delegate"), Some(&Value::Bool(true)));
+    assert_eq!(property(properties, "TasksMax"), Some(&Value::UInt64(100)));
+    let memory = if cgroups::is_v2() {
+        "MemoryMax"
+    } else {
+        "MemoryLimit"
+    };
+    assert_eq!(property(properties, memory), Some(&Value::UInt64(1 << 30)));
+    // a subscription is needed to be told when the job is done
+    assert!(!bus.calls("Subscribe").is_empty());
+
+    let failing = systemd::Unit::parse("system.slice:failing:c2", "c2").unwrap();
+    let err = systemd::start_unit(&failing, Pid::from_raw(4243), None).unwrap_err();
+    assert!(err.to_string().contains("ended with failed"), "{}", err);
+
+    systemd::set_unit_properties("test-c1.scope", &resources(r#"{"pids":{"limit":0}}"#)).unwrap();
+    let set = &bus.calls("SetUnitProperties")[0];
+    assert_eq!(set.body[0].as_str(), Some("test-c1.scope"));
+    assert_eq!(
+        property(&set.body[2], "TasksMax"),
+        Some(&Value::UInt64(u64::MAX))
+    );
+    // nothing to set, nothing to call
+    systemd::set_unit_properties("test-c1.scope", &resources("{}")).unwrap();
+    assert_eq!(bus.calls("SetUnitProperties").len(), 1);
+
+    systemd::stop_unit("test-c1.scope").unwrap();
+    assert_eq!(
+        bus.calls("StopUnit")[0].body[0].as_str(),
+        Some("test-c1.scope")
+    );
+    // a unit that already went away is as good as stopped
+    systemd::stop_unit("missing-c3.scope").unwrap();
+    let err = systemd::stop_unit("failing-c4.scope").unwrap_err();
+    assert!(err.to_string().contains("ended with failed"), "{}", err);
+}